@@ -0,0 +1,369 @@
+//! Archetype based storage.
+//! Entities with the same set of components live in the same table,
+//! adding or removing component moves entity into another table.
+//! This automates splitting of [`crate::Data`] into multiple data structs for sparse data.
+
+use crate::{Components, EcsError, Entity};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::any::{Any, TypeId};
+
+// Column of table with erased component type
+trait Column: Any {
+    fn new_empty(&self) -> Box<dyn Column>;
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column);
+    fn drop_row(&mut self, row: usize);
+}
+
+impl<T: 'static> Column for Vec<T> {
+    fn new_empty(&self) -> Box<dyn Column> {
+        Box::new(Vec::<T>::new())
+    }
+
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column) {
+        let dst: &mut dyn Any = dst;
+        dst.downcast_mut::<Vec<T>>()
+            .unwrap()
+            .push(self.swap_remove(row));
+    }
+
+    fn drop_row(&mut self, row: usize) {
+        self.swap_remove(row);
+    }
+}
+
+/// Table of entities that have the same set of components
+#[derive(Default)]
+pub struct Table {
+    entities: Vec<Entity>,
+    columns: BTreeMap<TypeId, Box<dyn Column>>,
+}
+
+impl Table {
+    /// Entities stored in this table, in the same order as components in columns
+    #[must_use]
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Check whether entities in this table have component T
+    #[must_use]
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.columns.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Get all components of type T in this table
+    #[must_use]
+    pub fn column<T: 'static>(&self) -> Option<&[T]> {
+        let column: &dyn Any = self.columns.get(&TypeId::of::<T>())?.as_ref();
+        column.downcast_ref::<Vec<T>>().map(Vec::as_slice)
+    }
+
+    /// Mutably get all components of type T in this table
+    #[must_use]
+    pub fn column_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
+        let column: &mut dyn Any = self.columns.get_mut(&TypeId::of::<T>())?.as_mut();
+        column.downcast_mut::<Vec<T>>().map(Vec::as_mut_slice)
+    }
+
+    /// Mutably get all components of several distinct types in this table at once
+    ///
+    /// ```
+    /// use ecs::{Archetypes, EcsError};
+    ///
+    /// struct Position(u64);
+    /// struct Velocity(u64);
+    ///
+    /// let mut world = Archetypes::new();
+    /// let player = world.entity();
+    /// world.insert(player, Position(10));
+    /// world.insert(player, Velocity(1));
+    ///
+    /// let table = world.tables_mut().find(|table| table.contains::<Velocity>()).unwrap();
+    /// let (positions, velocities) = table.columns_mut::<(Position, Velocity)>().unwrap();
+    /// positions[0].0 += velocities[0].0;
+    /// velocities[0].0 = 0;
+    /// assert!(matches!(
+    ///     table.columns_mut::<(Position, Position)>(),
+    ///     Err(EcsError::AliasedComponent(_))
+    /// ));
+    /// assert!(matches!(table.columns_mut::<(Position, u32)>(), Err(EcsError::UnknownComponent(_))));
+    /// assert_eq!(world.get::<Position>(player).unwrap().0, 11);
+    /// ```
+    /// # Errors
+    /// Returns error if component type is repeated or entities in this table do not have it.
+    pub fn columns_mut<C: Components>(&mut self) -> Result<C::ValuesMut<'_>, EcsError> {
+        C::columns_mut(self)
+    }
+
+    // Columns are boxed, so that pointer to column stays valid while other columns are borrowed
+    pub(crate) fn column_ptr<T: 'static>(&mut self) -> Option<*mut Vec<T>> {
+        let column: &mut dyn Any = self.columns.get_mut(&TypeId::of::<T>())?.as_mut();
+        column.downcast_mut::<Vec<T>>().map(core::ptr::from_mut)
+    }
+
+    // Removes row and returns entity that was moved into its place
+    fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// Archetype based data
+///
+/// Components do not need to implement Default, since entities are stored only in tables
+/// that have all of their components.
+///
+/// ```
+/// use ecs::Archetypes;
+///
+/// struct Position(u64, u64);
+/// struct Velocity(u64, u64);
+///
+/// let mut world = Archetypes::new();
+/// let player = world.entity();
+/// world.insert(player, Position(10, 20));
+/// world.insert(player, Velocity(1, 2));
+/// let rock = world.entity();
+/// world.insert(rock, Position(0, 0));
+///
+/// for table in world.tables_mut().filter(|table| table.contains::<Velocity>()) {
+///     let (positions, velocities) = table.columns_mut::<(Position, Velocity)>().unwrap();
+///     for (position, velocity) in positions.iter_mut().zip(velocities.iter()) {
+///         position.0 += velocity.0;
+///         position.1 += velocity.1;
+///     }
+/// }
+/// assert_eq!(world.get::<Position>(player).unwrap().0, 11);
+/// assert_eq!(world.query::<Position>().map(<[_]>::len).sum::<usize>(), 2);
+///
+/// assert_eq!(world.remove::<Velocity>(player).unwrap().1, 2);
+/// assert_eq!(world.query::<Velocity>().map(<[_]>::len).sum::<usize>(), 0);
+/// ```
+pub struct Archetypes {
+    // Generation of each entity slot, incremented on despawn
+    generations: Vec<u32>,
+    // Table and row of each entity
+    locations: Vec<(usize, usize)>,
+    // Indices of despawned entities, reused by next calls to entity
+    free: Vec<u32>,
+    tables: Vec<Table>,
+    // Sorted component types of each table
+    table_ids: BTreeMap<Vec<TypeId>, usize>,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self {
+            generations: Vec::new(),
+            locations: Vec::new(),
+            free: Vec::new(),
+            tables: alloc::vec![Table::default()],
+            table_ids: BTreeMap::from([(Vec::new(), 0)]),
+        }
+    }
+}
+
+impl Archetypes {
+    /// Initialize empty new system
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add new entity without components, reusing slot of despawned entity if there is one
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn entity(&mut self) -> Entity {
+        let location = (0, self.tables[0].entities.len());
+        let entity = if let Some(index) = self.free.pop() {
+            let i = usize::try_from(index).unwrap();
+            self.locations[i] = location;
+            Entity {
+                index,
+                generation: self.generations[i],
+            }
+        } else {
+            let index = u32::try_from(self.generations.len()).unwrap();
+            self.generations.push(0);
+            self.locations.push(location);
+            Entity {
+                index,
+                generation: 0,
+            }
+        };
+        self.tables[0].entities.push(entity);
+        entity
+    }
+
+    /// Check whether entity was not despawned yet
+    #[must_use]
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.i()) == Some(&entity.generation)
+    }
+
+    /// Remove entity together with all of its components.
+    /// Returns false if entity was already despawned.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let i = entity.i();
+        let (table, row) = self.locations[i];
+        let table = &mut self.tables[table];
+        for column in table.columns.values_mut() {
+            column.drop_row(row);
+        }
+        if let Some(moved) = table.swap_remove(row) {
+            self.locations[moved.i()].1 = row;
+        }
+        self.generations[i] = self.generations[i].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    /// Add component to existing entity, moving it into table with its new set of components.
+    /// Returns true if entity already had the component and it was overwritten.
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> bool {
+        let (table, row) = self.location(entity);
+        if let Some(column) = self.tables[table].column_mut::<T>() {
+            column[row] = component;
+            return true;
+        }
+        let mut types: Vec<TypeId> = self.tables[table].columns.keys().copied().collect();
+        types.push(TypeId::of::<T>());
+        types.sort_unstable();
+        let column: Box<dyn Column> = Box::new(Vec::<T>::new());
+        let target = self.table(types, table, Some((TypeId::of::<T>(), column)));
+        self.move_entity(entity, target);
+        self.tables[target]
+            .columns
+            .get_mut(&TypeId::of::<T>())
+            .map(|column| {
+                let column: &mut dyn Any = column.as_mut();
+                column.downcast_mut::<Vec<T>>().unwrap()
+            })
+            .unwrap()
+            .push(component);
+        false
+    }
+
+    /// Remove component from entity, moving it into table with its new set of components.
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        let (table, row) = self.location(entity);
+        let column: &mut dyn Any = self.tables[table]
+            .columns
+            .get_mut(&TypeId::of::<T>())?
+            .as_mut();
+        let component = column.downcast_mut::<Vec<T>>().unwrap().swap_remove(row);
+        let types = self.tables[table]
+            .columns
+            .keys()
+            .copied()
+            .filter(|&id| id != TypeId::of::<T>())
+            .collect();
+        let target = self.table(types, table, None);
+        self.move_entity(entity, target);
+        Some(component)
+    }
+
+    /// Get component of entity
+    #[must_use]
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        let (table, row) = self.locations[entity.i()];
+        self.tables[table].column::<T>().map(|column| &column[row])
+    }
+
+    /// Mutably get component of entity
+    #[must_use]
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        let (table, row) = self.locations[entity.i()];
+        self.tables[table]
+            .column_mut::<T>()
+            .map(|column| &mut column[row])
+    }
+
+    /// Query all values of single component type, one slice per table that has the component.
+    /// Tables without the component are skipped.
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = &[T]> {
+        self.tables.iter().filter_map(Table::column::<T>)
+    }
+
+    /// Mutably query all values of single component type, one slice per table that has the component.
+    /// Tables without the component are skipped.
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = &mut [T]> {
+        self.tables.iter_mut().filter_map(Table::column_mut::<T>)
+    }
+
+    /// Iterate over all tables, use [`Table::contains`] to skip tables without requested components
+    pub fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.tables.iter()
+    }
+
+    /// Mutably iterate over all tables
+    pub fn tables_mut(&mut self) -> impl Iterator<Item = &mut Table> {
+        self.tables.iter_mut()
+    }
+
+    #[track_caller]
+    fn location(&self, entity: Entity) -> (usize, usize) {
+        assert!(self.is_alive(entity), "{entity:?} is not alive");
+        self.locations[entity.i()]
+    }
+
+    // Find table with given sorted component types or create it
+    // from columns of source table and optionally one new column
+    fn table(
+        &mut self,
+        types: Vec<TypeId>,
+        source: usize,
+        new: Option<(TypeId, Box<dyn Column>)>,
+    ) -> usize {
+        if let Some(&table) = self.table_ids.get(&types) {
+            return table;
+        }
+        let mut columns: BTreeMap<TypeId, Box<dyn Column>> = self.tables[source]
+            .columns
+            .iter()
+            .filter(|(id, _)| types.contains(id))
+            .map(|(&id, column)| (id, column.new_empty()))
+            .collect();
+        if let Some((id, column)) = new {
+            columns.insert(id, column);
+        }
+        self.tables.push(Table {
+            entities: Vec::new(),
+            columns,
+        });
+        self.table_ids.insert(types, self.tables.len() - 1);
+        self.tables.len() - 1
+    }
+
+    // Move entity with all components that target table has,
+    // row of column that target does not have must be already removed
+    fn move_entity(&mut self, entity: Entity, target: usize) {
+        let (source, row) = self.locations[entity.i()];
+        let [source_table, target_table] = self.tables.get_disjoint_mut([source, target]).unwrap();
+        for (id, column) in &mut source_table.columns {
+            if let Some(dst) = target_table.columns.get_mut(id) {
+                column.move_row(row, dst.as_mut());
+            }
+        }
+        if let Some(moved) = source_table.swap_remove(row) {
+            self.locations[moved.i()].1 = row;
+        }
+        self.locations[entity.i()] = (target, target_table.entities.len());
+        target_table.entities.push(entity);
+    }
+}
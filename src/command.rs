@@ -0,0 +1,177 @@
+//! Deferred structural changes.
+
+use crate::reserve::Reservations;
+use crate::{Data, Entity, RefCount};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::Ordering;
+
+type Command<R> = Box<dyn FnOnce(&mut Data<R>)>;
+
+/// Buffer of structural changes, which are applied in order by [`Data::apply`].
+/// Entities spawned by commands are reserved like by [`Data::reserve_entity`],
+/// so their handles can be used right away and they are alive after next flush of data.
+///
+/// ```
+/// use ecs::{Data, Entity};
+///
+/// struct Health(u32);
+/// struct Corpse;
+///
+/// let mut world = Data::new();
+/// for health in [0, 10, 0] {
+///     let entity = world.entity();
+///     world.insert(entity, Health(health));
+/// }
+///
+/// let mut commands = world.commands();
+/// for (entity, health) in world.query::<(Entity, &mut Health)>() {
+///     if health.0 == 0 {
+///         let corpse = commands.spawn();
+///         commands.insert(corpse, Corpse);
+///         commands.despawn(entity);
+///     }
+/// }
+/// world.apply(commands);
+/// assert_eq!(world.query::<&Health>().count(), 1);
+/// assert_eq!(world.query::<&Corpse>().count(), 2);
+///
+/// let mut first = world.commands();
+/// let mut second = world.commands();
+/// let a = first.spawn();
+/// let b = second.spawn();
+/// let c = world.entity();
+/// assert!(a != b && b != c && a != c);
+/// world.apply(second);
+/// world.apply(first);
+/// assert!(world.is_alive(a) && world.is_alive(b));
+/// ```
+pub struct Commands<R: RefCount = u8> {
+    reserved: Arc<Reservations>,
+    // Number of flushes of data when commands were created
+    flushes: usize,
+    // Free list of data when commands were created
+    free: Vec<Entity>,
+    // Number of entity slots of data when commands were created
+    slots: usize,
+    queue: Vec<Command<R>>,
+}
+
+impl<R: RefCount> Commands<R> {
+    /// Reserve new entity, which is spawned by next [`Data::apply`] or other flush of data
+    /// # Panics
+    /// Panics if data was flushed since commands were created, e.g. by [`Data::entity`].
+    #[track_caller]
+    #[must_use]
+    pub fn spawn(&mut self) -> Entity {
+        assert_eq!(
+            self.flushes,
+            self.reserved.flushes.load(Ordering::Relaxed),
+            "commands can not spawn entities after data was flushed"
+        );
+        let n = self.reserved.count.fetch_add(1, Ordering::Relaxed);
+        if let Some(i) = n.checked_sub(self.free.len()) {
+            return Entity {
+                index: u32::try_from(self.slots + i).unwrap(),
+                generation: 0,
+            };
+        }
+        self.free[self.free.len() - 1 - n]
+    }
+
+    /// Add component to entity, see [`Data::insert`]
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        self.push(move |data| {
+            data.insert(entity, component);
+        });
+    }
+
+    /// Remove component from entity, see [`Data::remove`]
+    pub fn remove<T: 'static>(&mut self, entity: Entity) {
+        self.push(move |data| {
+            data.remove::<T>(entity);
+        });
+    }
+
+    /// Despawn entity, see [`Data::despawn`]
+    pub fn despawn(&mut self, entity: Entity) {
+        self.push(move |data| {
+            data.despawn(entity);
+        });
+    }
+
+    /// Increase reference count of entity, see [`Data::retain`]
+    pub fn retain(&mut self, entity: Entity) {
+        self.push(move |data| data.retain(entity));
+    }
+
+    /// Decrease reference count of entity, see [`Data::release`]
+    pub fn release(&mut self, entity: Entity) {
+        self.push(move |data| {
+            data.release(entity);
+        });
+    }
+
+    /// Number of recorded commands
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Check whether no commands were recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn push(&mut self, command: impl FnOnce(&mut Data<R>) + 'static) {
+        self.queue.push(Box::new(command));
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Create command buffer
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn commands(&self) -> Commands<R> {
+        Commands {
+            reserved: self.reserved.clone(),
+            flushes: self.reserved.flushes.load(Ordering::Relaxed),
+            free: self
+                .free
+                .iter()
+                .map(|&index| Entity {
+                    index,
+                    generation: self.generations[usize::try_from(index).unwrap()],
+                })
+                .collect(),
+            slots: self.generations.len(),
+            queue: Vec::new(),
+        }
+    }
+
+    /// Spawn reserved entities, then apply all commands in order in which they were recorded.
+    ///
+    /// ```should_panic
+    /// use ecs::Data;
+    ///
+    /// let mut world = Data::new();
+    /// let other = Data::new();
+    /// let mut commands = other.commands();
+    /// let _ = commands.spawn();
+    /// world.apply(commands);
+    /// ```
+    /// # Panics
+    /// Panics if commands were created by another data, since their entities were reserved there,
+    /// or if command panics, e.g. if it inserts component into entity that is not alive.
+    #[track_caller]
+    pub fn apply(&mut self, commands: Commands<R>) {
+        assert!(
+            Arc::ptr_eq(&commands.reserved, &self.reserved),
+            "commands were created by another data"
+        );
+        self.flush_entities();
+        for command in commands.queue {
+            command(self);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Double-buffered event queues.
+
+use crate::{Data, RefCount};
+use alloc::{boxed::Box, vec::Vec};
+use core::any::{Any, TypeId};
+use core::marker::PhantomData;
+
+/// Queue of events of type T.
+/// Events stay readable until they survive two calls to [`Events::update`],
+/// so readers that run once per update do not miss events sent after they ran.
+pub struct Events<T> {
+    // Events sent before last update
+    previous: Vec<T>,
+    // Events sent since last update
+    current: Vec<T>,
+    // Number of events dropped before previous ones
+    offset: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    /// Add event to the queue
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Drop events sent before previous update
+    pub fn update(&mut self) {
+        self.offset += self.previous.len();
+        self.previous = core::mem::take(&mut self.current);
+    }
+
+    /// Number of events in the queue
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Check whether the queue has no events
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over all events in the queue, in order in which they were sent
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+}
+
+/// Cursor of single consumer of events, so that each event is read only once.
+/// New reader reads all events that are still in the queue.
+pub struct EventReader<T> {
+    // Number of events sent before next unread event
+    next: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    /// Create reader that reads all events still in the queue
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read events sent since last read, events dropped since then are skipped
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> + use<'a, T> {
+        let skip = self.next.saturating_sub(events.offset);
+        self.next = events.offset + events.len();
+        events.iter().skip(skip)
+    }
+}
+
+// Event queue with erased event type, so that all queues can be updated together
+pub(crate) trait AnyEvents: Any {
+    fn update(&mut self);
+}
+
+impl<T: 'static> AnyEvents for Events<T> {
+    fn update(&mut self) {
+        Events::update(self);
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Send event, creating queue of its type if it does not exist
+    ///
+    /// ```
+    /// use ecs::{Data, EventReader};
+    ///
+    /// struct Collision(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let mut physics = EventReader::<Collision>::new();
+    /// let mut audio = EventReader::<Collision>::new();
+    ///
+    /// world.send(Collision(1));
+    /// let read: Vec<_> = world.read_events(&mut physics).map(|c| c.0).collect();
+    /// assert_eq!(read, [1]);
+    /// world.update_events();
+    /// world.send(Collision(2));
+    /// assert_eq!(world.read_events(&mut physics).map(|c| c.0).collect::<Vec<_>>(), [2]);
+    /// assert_eq!(world.read_events(&mut audio).map(|c| c.0).collect::<Vec<_>>(), [1, 2]);
+    ///
+    /// world.update_events();
+    /// world.update_events();
+    /// assert!(world.events::<Collision>().unwrap().is_empty());
+    /// world.send(Collision(3));
+    /// assert_eq!(world.read_events(&mut audio).map(|c| c.0).collect::<Vec<_>>(), [3]);
+    /// ```
+    #[allow(clippy::missing_panics_doc)]
+    pub fn send<T: 'static>(&mut self, event: T) {
+        let events: &mut dyn Any = self
+            .events
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Events::<T>::default()))
+            .as_mut();
+        events.downcast_mut::<Events<T>>().unwrap().send(event);
+    }
+
+    /// Get queue of events of type T, None if no event of type T was sent yet
+    #[must_use]
+    pub fn events<T: 'static>(&self) -> Option<&Events<T>> {
+        let events: &dyn Any = self.events.get(&TypeId::of::<T>())?.as_ref();
+        events.downcast_ref()
+    }
+
+    /// Mutably get queue of events of type T, None if no event of type T was sent yet
+    #[must_use]
+    pub fn events_mut<T: 'static>(&mut self) -> Option<&mut Events<T>> {
+        let events: &mut dyn Any = self.events.get_mut(&TypeId::of::<T>())?.as_mut();
+        events.downcast_mut()
+    }
+
+    /// Read events of type T sent since last read by reader
+    pub fn read_events<'a, T: 'static>(
+        &'a self,
+        reader: &mut EventReader<T>,
+    ) -> impl Iterator<Item = &'a T> + use<'a, T, R> {
+        self.events::<T>()
+            .map(|events| reader.read(events))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Update queues of all event types, dropping events sent before previous update.
+    /// Should be called once per frame.
+    pub fn update_events(&mut self) {
+        for events in self.events.values_mut() {
+            events.update();
+        }
+    }
+}
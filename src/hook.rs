@@ -0,0 +1,170 @@
+//! Component lifecycle hooks.
+
+use crate::{Data, EcsError, Entity, RefCount};
+use alloc::boxed::Box;
+use core::any::TypeId;
+
+/// World handle passed to hooks.
+/// Components can be read and modified, but entities and components can not be added or removed,
+/// so hooks do not trigger other hooks.
+pub struct HookWorld<'a, R: RefCount = u8> {
+    data: &'a mut Data<R>,
+}
+
+impl<R: RefCount> HookWorld<'_, R> {
+    /// Check whether entity was not despawned yet
+    #[must_use]
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.data.is_alive(entity)
+    }
+
+    /// Check whether entity has component
+    #[must_use]
+    pub fn contains<T: 'static>(&self, entity: Entity) -> bool {
+        self.data.contains::<T>(entity)
+    }
+
+    /// Get component of entity
+    /// # Errors
+    /// Returns error if entity is not alive, component type has no storage or entity does not have the component.
+    pub fn get<T: 'static>(&self, entity: Entity) -> Result<&T, EcsError> {
+        self.data.get(entity)
+    }
+
+    /// Mutably get component of entity
+    /// # Errors
+    /// Returns error if entity is not alive, component type has no storage or entity does not have the component.
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Result<&mut T, EcsError> {
+        self.data.get_mut(entity)
+    }
+
+    /// Get resource
+    /// # Errors
+    /// Returns error if resource was not inserted.
+    pub fn resource<T: 'static>(&self) -> Result<&T, EcsError> {
+        self.data.resource()
+    }
+
+    /// Mutably get resource
+    /// # Errors
+    /// Returns error if resource was not inserted.
+    pub fn resource_mut<T: 'static>(&mut self) -> Result<&mut T, EcsError> {
+        self.data.resource_mut()
+    }
+
+    /// Current tick of the world
+    #[must_use]
+    pub fn tick(&self) -> u32 {
+        self.data.tick()
+    }
+}
+
+type Hook<R> = Box<dyn FnMut(HookWorld<'_, R>, Entity)>;
+
+// Hooks of single component type
+pub(crate) struct Hooks<R: RefCount> {
+    add: Option<Hook<R>>,
+    insert: Option<Hook<R>>,
+    remove: Option<Hook<R>>,
+}
+
+impl<R: RefCount> Default for Hooks<R> {
+    fn default() -> Self {
+        Self {
+            add: None,
+            insert: None,
+            remove: None,
+        }
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Set hook that runs after component T is added to entity that did not have it.
+    /// Replaces previous on add hook of T.
+    ///
+    /// Hooks keep external state in sync with components:
+    ///
+    /// ```
+    /// use ecs::Data;
+    /// use std::{cell::RefCell, rc::Rc};
+    ///
+    /// struct RigidBody(f32);
+    ///
+    /// let bodies = Rc::new(RefCell::new(Vec::new()));
+    /// let mut world = Data::new();
+    /// let added = bodies.clone();
+    /// world.on_add::<RigidBody>(move |_, entity| added.borrow_mut().push((entity, 0.)));
+    /// let inserted = bodies.clone();
+    /// world.on_insert::<RigidBody>(move |world, entity| {
+    ///     let mass = world.get::<RigidBody>(entity).unwrap().0;
+    ///     for body in inserted.borrow_mut().iter_mut().filter(|(e, _)| *e == entity) {
+    ///         body.1 = mass;
+    ///     }
+    /// });
+    /// let removed = bodies.clone();
+    /// world.on_remove::<RigidBody>(move |_, entity| removed.borrow_mut().retain(|(e, _)| *e != entity));
+    ///
+    /// let ball = world.entity();
+    /// world.insert(ball, RigidBody(1.));
+    /// let barrel = world.entity();
+    /// world.insert(barrel, RigidBody(5.));
+    /// world.insert(ball, RigidBody(2.));
+    /// assert_eq!(*bodies.borrow(), [(ball, 2.), (barrel, 5.)]);
+    ///
+    /// world.remove::<RigidBody>(ball);
+    /// world.despawn(barrel);
+    /// assert!(bodies.borrow().is_empty());
+    /// ```
+    pub fn on_add<T: 'static>(&mut self, hook: impl FnMut(HookWorld<'_, R>, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().add = Some(Box::new(hook));
+    }
+
+    /// Set hook that runs after component T is inserted into entity,
+    /// both when it is added and when it overwrites previous component.
+    /// Runs after on add hook. Replaces previous on insert hook of T.
+    pub fn on_insert<T: 'static>(&mut self, hook: impl FnMut(HookWorld<'_, R>, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().insert = Some(Box::new(hook));
+    }
+
+    /// Set hook that runs before component T is removed from entity,
+    /// either by [`Data::remove`] or when entity is despawned.
+    /// Components removed directly from storage returned by [`Data::storage_mut`] do not run hooks.
+    /// Replaces previous on remove hook of T.
+    pub fn on_remove<T: 'static>(&mut self, hook: impl FnMut(HookWorld<'_, R>, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().remove = Some(Box::new(hook));
+    }
+
+    // Hooks are taken out of data while they run, HookWorld can not change them
+    pub(crate) fn run_add_hooks<T: 'static>(&mut self, entity: Entity, added: bool) {
+        let mut hooks = core::mem::take(&mut self.hooks);
+        if let Some(hooks) = hooks.get_mut(&TypeId::of::<T>()) {
+            if let Some(hook) = hooks.add.as_mut().filter(|_| added) {
+                hook(HookWorld { data: self }, entity);
+            }
+            if let Some(hook) = &mut hooks.insert {
+                hook(HookWorld { data: self }, entity);
+            }
+        }
+        self.hooks = hooks;
+    }
+
+    // Run on remove hooks of all component types entity has, or only of type id if given
+    pub(crate) fn run_remove_hooks(&mut self, entity: Entity, id: Option<TypeId>) {
+        let mut hooks = core::mem::take(&mut self.hooks);
+        for (hook_id, hooks) in &mut hooks {
+            let Some(hook) = &mut hooks.remove else {
+                continue;
+            };
+            if id.is_some_and(|id| id != *hook_id)
+                || !self
+                    .components
+                    .get(hook_id)
+                    .is_some_and(|column| column.storage.contains_item(entity))
+            {
+                continue;
+            }
+            hook(HookWorld { data: self }, entity);
+        }
+        self.hooks = hooks;
+    }
+}
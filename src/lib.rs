@@ -1,10 +1,18 @@
 //! Very simple no-std ECS.
-//! Entities are just u32, componenents can be all types that implement Default + 'static
+//! Entities are u32 index with u32 generation, componenents can be all types that are 'static
 //! This ECS is meant to be used with data where most components are shared by all entities (dense data).
-//! If this is not the case (sparse data), create multiple data structs.
+//! If this is not the case (sparse data), create multiple data structs,
+//! or choose sparse storage for rare components with `Data::register`,
+//! or use archetype based [`Archetypes`] which groups entities by their set of components.
+//! Functions whose parameters are queries, resources and commands can run as systems, see [`system`],
+//! which are ordered into stages by [`Schedule`].
 //! Compared to using raw `Vec<T>` there are two overheads:
-//! 1. query makes single dynamic function call (i. e. one vtable lookup)
-//! 2. data contains reference count for each entity, but it is purely manual and only 1 byte per entity, thus max is 255 references of one entity
+//! 1. values makes single dynamic function call (i. e. one vtable lookup), query makes one per component of each entity.
+//!    Values are available only for component types registered as [`Dense<T>`] with `Data::register`,
+//!    since components are stored in [`Slots<T>`] by default, which can be accessed only by entity or query.
+//! 2. data contains reference count for each entity, by default only 1 byte per entity, thus max is 255 references of one entity.
+//!    Wider counter can be chosen with `Data::<u16>::default()` or `Data::<u32>::default()`.
+//!    Entity is despawned once its reference count drops to zero.
 
 #![no_std]
 #![deny(clippy::pedantic)]
@@ -12,22 +20,48 @@
 extern crate alloc;
 use core::any::TypeId;
 
+mod archetype;
+mod command;
+mod event;
+mod hook;
+mod observer;
+mod query;
+mod reserve;
+mod schedule;
+pub mod system;
+
+pub use archetype::{Archetypes, Table};
+pub use command::Commands;
+pub use event::{EventReader, Events};
+pub use hook::HookWorld;
+pub use query::{
+    Added, Changed, Components, Filter, Or, Query, QueryIter, ReadOnlyQuery, With, Without,
+};
+pub use reserve::EntityReserver;
+pub use schedule::{Schedule, Stage, SystemId};
+
 /// Entity
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
-pub struct Entity(u32);
+///
+/// Index of entity slot together with generation of that slot,
+/// so that handles of despawned entities are not confused with entities reusing their slot.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
 
 /// Data
 ///
 /// ```
-/// use ecs::Data;
+/// use ecs::{Data, Dense};
 ///
 /// #[derive(Default)]
 /// struct Position(u64, u64);
 ///
-/// #[derive(Default)]
 /// struct Velocity(f64, f64);
 ///
 /// let mut world = Data::new();
+/// world.register::<Dense<Position>>();
 /// let player = world.entity();
 /// world.insert(player, Position(10, 20));
 /// world.insert(player, Velocity(10., 20.));
@@ -35,15 +69,31 @@ pub struct Entity(u32);
 /// let player2 = world.entity();
 /// world.insert(player2, Position(10, 20));
 ///
-/// world.query_mut::<Position>().unwrap()[player.i()].1 += 1;
+/// world.values_mut::<Position>().unwrap()[player.i()].1 += 1;
 /// ```
 #[derive(Default)]
-pub struct Data {
-    // This can be either reference count or generational index, depending on usecase
-    rc: alloc::vec::Vec<u8>,
-    // we can have both dense components with vec
-    // and sparse componenets with BTreeMap<Entity, impl Component>
-    components: alloc::collections::BTreeMap<TypeId, alloc::boxed::Box<dyn Storage>>,
+pub struct Data<R: RefCount = u8> {
+    // Reference count of each entity, zero for despawned entities
+    rc: alloc::vec::Vec<R>,
+    // Generation of each entity slot, incremented on despawn
+    generations: alloc::vec::Vec<u32>,
+    // Indices of despawned entities, reused by next calls to entity
+    free: alloc::vec::Vec<u32>,
+    // we can have both dense components with Slots<T> or Dense<T>
+    // and sparse componenets with BTreeMap<Entity, Box<impl Component>>
+    components: alloc::collections::BTreeMap<TypeId, Column>,
+    // Components are stamped with this tick when they are added or mutably accessed
+    tick: u32,
+    // Lifecycle hooks of component types
+    hooks: alloc::collections::BTreeMap<TypeId, hook::Hooks<R>>,
+    // Observers of custom events and events waiting for flush
+    observers: observer::Observers<R>,
+    // Single value of each resource type
+    resources: alloc::collections::BTreeMap<TypeId, alloc::boxed::Box<dyn core::any::Any>>,
+    // Queue of each event type
+    events: alloc::collections::BTreeMap<TypeId, alloc::boxed::Box<dyn event::AnyEvents>>,
+    // Entities reserved through shared reference since last flush, shared with commands
+    reserved: alloc::sync::Arc<reserve::Reservations>,
 }
 
 impl Entity {
@@ -52,17 +102,534 @@ impl Entity {
     /// Panics if u32 can not be converted into usize.
     #[must_use]
     pub fn i(self) -> usize {
-        self.0.try_into().unwrap()
+        self.index.try_into().unwrap()
+    }
+
+    /// Get generation of entity slot this entity was created in.
+    #[must_use]
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+/// Reference counter of entities
+pub trait RefCount: Copy + Eq + Default + 'static {
+    /// Count of newly created entity
+    const ONE: Self;
+    /// Increment, returns None on overflow
+    fn increment(self) -> Option<Self>;
+    /// Decrement, returns None on underflow
+    fn decrement(self) -> Option<Self>;
+}
+
+macro_rules! impl_ref_count {
+    ($($t:ty),*) => {
+        $(impl RefCount for $t {
+            const ONE: Self = 1;
+            fn increment(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+            fn decrement(self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+        })*
+    };
+}
+
+impl_ref_count!(u8, u16, u32);
+
+/// Error returned by fallible operations of [`Data`] and [`Schedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcsError {
+    /// Entity was despawned
+    DeadEntity(Entity),
+    /// Reference count of entity would exceed maximum of its counter type
+    RefCountOverflow(Entity),
+    /// Component type has no storage
+    UnknownComponent(&'static str),
+    /// Entity does not have component
+    MissingComponent(Entity, &'static str),
+    /// Component type is not stored in [`Dense`] storage
+    NotDense(&'static str),
+    /// Component type was requested more than once
+    AliasedComponent(&'static str),
+    /// Resource was not inserted
+    MissingResource(&'static str),
+    /// System is part of cycle of ordering constraints
+    SystemCycle(&'static str),
+    /// First system is constrained to run before second system of earlier stage
+    StageOrder(&'static str, &'static str),
+}
+
+impl core::fmt::Display for EcsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EcsError::DeadEntity(entity) => write!(f, "{entity:?} is not alive"),
+            EcsError::RefCountOverflow(entity) => {
+                write!(f, "reference count of {entity:?} overflowed")
+            }
+            EcsError::UnknownComponent(name) => write!(f, "component {name} has no storage"),
+            EcsError::MissingComponent(entity, name) => {
+                write!(f, "{entity:?} does not have component {name}")
+            }
+            EcsError::NotDense(name) => write!(f, "component {name} is not stored in Dense"),
+            EcsError::AliasedComponent(name) => {
+                write!(f, "component {name} was requested more than once")
+            }
+            EcsError::MissingResource(name) => write!(f, "resource {name} was not inserted"),
+            EcsError::SystemCycle(name) => {
+                write!(f, "system {name} is part of ordering cycle")
+            }
+            EcsError::StageOrder(a, b) => {
+                write!(
+                    f,
+                    "system {a} can not run before system {b} of earlier stage"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for EcsError {}
+
+/// Storage of all components of single type
+///
+/// Storage of each component type can be chosen with [`Data::register`]:
+/// - [`Slots<T>`] holds slot for every entity, slots of entities without the component are uninitialized
+/// - [`Dense<T>`] holds value for every entity, components that were not inserted are `T::default()`
+/// - `BTreeMap<Entity, Box<T>>` is sparse, it holds boxed values only for entities that have the component
+/// - [`SparseSet<T>`] holds values only for entities that have the component, packed in single array
+pub trait Storage: core::any::Any {
+    /// Component type
+    type Item: 'static;
+    /// Called for every newly created entity slot
+    fn push_item(&mut self);
+    /// Get component of entity, None if entity does not have the component
+    fn get_item(&self, entity: Entity) -> Option<&Self::Item>;
+    /// Mutably get component of entity
+    fn get_item_mut(&mut self, entity: Entity) -> Option<&mut Self::Item>;
+    /// Get pointer to component of entity without borrowing components of other entities,
+    /// so that pointers returned earlier stay valid until component is inserted or removed
+    fn get_item_ptr(&mut self, entity: Entity) -> Option<*mut Self::Item>;
+    /// Check whether entity has the component without reading the component
+    fn contains_item(&self, entity: Entity) -> bool;
+    /// Insert component of entity, returns previous component
+    fn insert_item(&mut self, entity: Entity, component: Self::Item) -> Option<Self::Item>;
+    /// Remove component of entity, returns removed component
+    fn remove_item(&mut self, entity: Entity) -> Option<Self::Item>;
+}
+
+// Set of entity indices
+#[derive(Debug, Clone, Default)]
+struct Bitset(alloc::vec::Vec<u64>);
+
+impl Bitset {
+    fn contains(&self, i: usize) -> bool {
+        self.0
+            .get(i / 64)
+            .is_some_and(|word| word >> (i % 64) & 1 == 1)
+    }
+
+    // Returns true if i was not in the set
+    fn insert(&mut self, i: usize) -> bool {
+        if self.0.len() <= i / 64 {
+            self.0.resize(i / 64 + 1, 0);
+        }
+        let inserted = !self.contains(i);
+        self.0[i / 64] |= 1 << (i % 64);
+        inserted
+    }
+
+    // Returns true if i was in the set
+    fn remove(&mut self, i: usize) -> bool {
+        let removed = self.contains(i);
+        if removed {
+            self.0[i / 64] &= !(1 << (i % 64));
+        }
+        removed
+    }
+}
+
+/// Dense storage
+///
+/// Holds value for every entity together with bitset of entities that have the component,
+/// values of entities without the component are `T::default()`.
+///
+/// ```
+/// use ecs::{Data, Dense};
+///
+/// #[derive(Default)]
+/// struct Speed(u32);
+///
+/// let mut world = Data::new();
+/// world.register::<Dense<Speed>>();
+/// let car = world.entity();
+/// world.insert(car, Speed(30));
+/// let tree = world.entity();
+///
+/// let speed = world.storage::<Dense<Speed>>().unwrap();
+/// assert_eq!(speed.values().len(), 2);
+/// assert!(speed.contains(car));
+/// assert!(!speed.contains(tree));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dense<T> {
+    values: alloc::vec::Vec<T>,
+    present: Bitset,
+}
+
+impl<T> Default for Dense<T> {
+    fn default() -> Self {
+        Self {
+            values: alloc::vec::Vec::new(),
+            present: Bitset::default(),
+        }
+    }
+}
+
+impl<T> Dense<T> {
+    /// Values of all entities, indexed by [`Entity::i`]
+    #[must_use]
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Mutable values of all entities, indexed by [`Entity::i`]
+    #[must_use]
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    /// Check whether entity has the component
+    #[must_use]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.present.contains(entity.i())
+    }
+}
+
+impl<T: Default + 'static> Storage for Dense<T> {
+    type Item = T;
+
+    fn push_item(&mut self) {
+        self.values.push(Default::default());
+    }
+
+    fn get_item(&self, entity: Entity) -> Option<&T> {
+        self.contains(entity).then(|| &self.values[entity.i()])
+    }
+
+    fn get_item_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.contains(entity).then(|| &mut self.values[entity.i()])
+    }
+
+    fn get_item_ptr(&mut self, entity: Entity) -> Option<*mut T> {
+        // Values hold slot for every entity
+        self.contains(entity)
+            .then(|| unsafe { self.values.as_mut_ptr().add(entity.i()) })
+    }
+
+    fn contains_item(&self, entity: Entity) -> bool {
+        self.contains(entity)
+    }
+
+    fn insert_item(&mut self, entity: Entity, component: T) -> Option<T> {
+        let previous = core::mem::replace(&mut self.values[entity.i()], component);
+        (!self.present.insert(entity.i())).then_some(previous)
+    }
+
+    fn remove_item(&mut self, entity: Entity) -> Option<T> {
+        self.present
+            .remove(entity.i())
+            .then(|| core::mem::take(&mut self.values[entity.i()]))
+    }
+}
+
+// Values are boxed, so that pointers to them stay valid while nodes of the map are borrowed
+impl<T: 'static> Storage for alloc::collections::BTreeMap<Entity, alloc::boxed::Box<T>> {
+    type Item = T;
+
+    fn push_item(&mut self) {}
+
+    fn get_item(&self, entity: Entity) -> Option<&T> {
+        alloc::collections::BTreeMap::get(self, &entity).map(|value| &**value)
+    }
+
+    fn get_item_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        alloc::collections::BTreeMap::get_mut(self, &entity).map(|value| &mut **value)
+    }
+
+    fn get_item_ptr(&mut self, entity: Entity) -> Option<*mut T> {
+        alloc::collections::BTreeMap::get_mut(self, &entity).map(|value| &raw mut **value)
+    }
+
+    fn contains_item(&self, entity: Entity) -> bool {
+        alloc::collections::BTreeMap::contains_key(self, &entity)
+    }
+
+    fn insert_item(&mut self, entity: Entity, component: T) -> Option<T> {
+        alloc::collections::BTreeMap::insert(self, entity, alloc::boxed::Box::new(component))
+            .map(|value| *value)
+    }
+
+    fn remove_item(&mut self, entity: Entity) -> Option<T> {
+        alloc::collections::BTreeMap::remove(self, &entity).map(|value| *value)
+    }
+}
+
+/// Slot storage
+///
+/// Holds slot for every entity together with bitset of entities that have the component,
+/// slots of entities without the component are left uninitialized, so components do not need Default.
+/// This is the storage of components that were not registered with [`Data::register`].
+///
+/// ```
+/// use ecs::{Data, Slots};
+/// use std::num::NonZeroU32;
+///
+/// struct Handle(NonZeroU32);
+///
+/// let mut world = Data::new();
+/// let texture = world.entity();
+/// let empty = world.entity();
+/// world.insert(texture, Handle(NonZeroU32::new(7).unwrap()));
+///
+/// let handles = world.storage::<Slots<Handle>>().unwrap();
+/// assert!(handles.contains(texture));
+/// assert!(!handles.contains(empty));
+/// ```
+pub struct Slots<T> {
+    values: alloc::vec::Vec<core::mem::MaybeUninit<T>>,
+    present: Bitset,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self {
+            values: alloc::vec::Vec::new(),
+            present: Bitset::default(),
+        }
+    }
+}
+
+impl<T> Drop for Slots<T> {
+    fn drop(&mut self) {
+        for (i, value) in self.values.iter_mut().enumerate() {
+            if self.present.contains(i) {
+                // Slots in present bitset are initialized
+                unsafe { value.assume_init_drop() };
+            }
+        }
+    }
+}
+
+impl<T> Slots<T> {
+    /// Check whether entity has the component
+    #[must_use]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.present.contains(entity.i())
+    }
+}
+
+impl<T: 'static> Storage for Slots<T> {
+    type Item = T;
+
+    fn push_item(&mut self) {
+        self.values.push(core::mem::MaybeUninit::uninit());
+    }
+
+    fn get_item(&self, entity: Entity) -> Option<&T> {
+        // Slots in present bitset are initialized
+        self.contains(entity)
+            .then(|| unsafe { self.values[entity.i()].assume_init_ref() })
+    }
+
+    fn get_item_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.contains(entity)
+            .then(|| unsafe { self.values[entity.i()].assume_init_mut() })
+    }
+
+    fn get_item_ptr(&mut self, entity: Entity) -> Option<*mut T> {
+        // Values hold slot for every entity
+        self.contains(entity)
+            .then(|| unsafe { self.values.as_mut_ptr().add(entity.i()).cast() })
+    }
+
+    fn contains_item(&self, entity: Entity) -> bool {
+        self.contains(entity)
+    }
+
+    fn insert_item(&mut self, entity: Entity, component: T) -> Option<T> {
+        let slot = &mut self.values[entity.i()];
+        if self.present.insert(entity.i()) {
+            slot.write(component);
+            None
+        } else {
+            Some(core::mem::replace(
+                unsafe { slot.assume_init_mut() },
+                component,
+            ))
+        }
+    }
+
+    fn remove_item(&mut self, entity: Entity) -> Option<T> {
+        self.present
+            .remove(entity.i())
+            .then(|| unsafe { self.values[entity.i()].assume_init_read() })
+    }
+}
+
+/// Sparse set storage
+///
+/// Components are packed in dense array together with their entities,
+/// sparse array maps entity index to position in dense arrays.
+/// Insert, remove and lookup are O(1) and iteration goes over entities that have the component only.
+///
+/// ```
+/// use ecs::{Data, SparseSet};
+///
+/// #[derive(Default)]
+/// struct Health(u32);
+///
+/// let mut world = Data::new();
+/// world.register::<SparseSet<Health>>();
+/// let entities: Vec<_> = (0..10).map(|_| world.entity()).collect();
+/// for entity in entities.iter().step_by(3) {
+///     world.insert(*entity, Health(100));
+/// }
+/// world.despawn(entities[3]);
+///
+/// let health = world.storage::<SparseSet<Health>>().unwrap();
+/// assert_eq!(health.len(), 3);
+/// assert!(health.iter().all(|(entity, health)| entity.i() % 3 == 0 && health.0 == 100));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SparseSet<T> {
+    values: alloc::vec::Vec<T>,
+    entities: alloc::vec::Vec<Entity>,
+    sparse: alloc::vec::Vec<Option<u32>>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            values: alloc::vec::Vec::new(),
+            entities: alloc::vec::Vec::new(),
+            sparse: alloc::vec::Vec::new(),
+        }
     }
 }
 
-trait Storage: 'static {
+impl<T> SparseSet<T> {
+    /// Number of entities that have the component
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check whether no entity has the component
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Entities that have the component, in the same order as values
+    #[must_use]
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Packed values of the component
+    #[must_use]
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Mutable packed values of the component
+    #[must_use]
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    /// Iterate over entities together with their components
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(&self.values)
+    }
+
+    /// Mutably iterate over entities together with their components
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.entities.iter().copied().zip(&mut self.values)
+    }
+
+    fn position(&self, entity: Entity) -> Option<usize> {
+        let position = usize::try_from((*self.sparse.get(entity.i())?)?).unwrap();
+        (self.entities[position] == entity).then_some(position)
+    }
+}
+
+impl<T: 'static> Storage for SparseSet<T> {
+    type Item = T;
+
+    fn push_item(&mut self) {
+        self.sparse.push(None);
+    }
+
+    fn get_item(&self, entity: Entity) -> Option<&T> {
+        self.position(entity).map(|position| &self.values[position])
+    }
+
+    fn get_item_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.position(entity)
+            .map(|position| &mut self.values[position])
+    }
+
+    fn get_item_ptr(&mut self, entity: Entity) -> Option<*mut T> {
+        self.position(entity)
+            .map(|position| unsafe { self.values.as_mut_ptr().add(position) })
+    }
+
+    fn contains_item(&self, entity: Entity) -> bool {
+        self.position(entity).is_some()
+    }
+
+    fn insert_item(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(position) = self.position(entity) {
+            return Some(core::mem::replace(&mut self.values[position], component));
+        }
+        self.sparse[entity.i()] = Some(u32::try_from(self.values.len()).unwrap());
+        self.entities.push(entity);
+        self.values.push(component);
+        None
+    }
+
+    fn remove_item(&mut self, entity: Entity) -> Option<T> {
+        let position = self.position(entity)?;
+        self.sparse[entity.i()] = None;
+        self.entities.swap_remove(position);
+        if let Some(moved) = self.entities.get(position) {
+            self.sparse[moved.i()] = Some(u32::try_from(position).unwrap());
+        }
+        Some(self.values.swap_remove(position))
+    }
+}
+
+// Storage with erased component type, so that all storages can be stored together
+trait AnyStorage {
     fn push_item(&mut self);
+    fn contains_item(&self, entity: Entity) -> bool;
+    fn remove_item(&mut self, entity: Entity);
 }
 
-impl<T: Default + 'static> Storage for alloc::vec::Vec<T> {
+impl<T: 'static> AnyStorage for alloc::boxed::Box<dyn Storage<Item = T>> {
     fn push_item(&mut self) {
-        self.push(Default::default());
+        self.as_mut().push_item();
+    }
+
+    fn contains_item(&self, entity: Entity) -> bool {
+        self.as_ref().contains_item(entity)
+    }
+
+    fn remove_item(&mut self, entity: Entity) {
+        self.as_mut().remove_item(entity);
     }
 }
 
@@ -71,13 +638,54 @@ trait Downcast {
     fn downcast_mut<T: 'static>(&mut self) -> &mut T;
 }
 
-impl Downcast for alloc::boxed::Box<dyn Storage> {
+// Storage of component T is always stored as Box<dyn Storage<Item = T>>
+// under key TypeId::of::<T>(), so it can be downcasted without checks
+impl Downcast for alloc::boxed::Box<dyn AnyStorage> {
     fn downcast_ref<T: 'static>(&self) -> &T {
-        unsafe { &*(self.as_ref() as *const dyn Storage).cast() }
+        unsafe { &*core::ptr::from_ref::<dyn AnyStorage>(self.as_ref()).cast() }
     }
 
     fn downcast_mut<T: 'static>(&mut self) -> &mut T {
-        unsafe { &mut *(self.as_mut() as *mut dyn Storage).cast() }
+        unsafe { &mut *core::ptr::from_mut::<dyn AnyStorage>(self.as_mut()).cast() }
+    }
+}
+
+// Storage of single component type together with ticks
+// at which component of each entity was added and last mutably accessed
+struct Column {
+    storage: alloc::boxed::Box<dyn AnyStorage>,
+    added: alloc::vec::Vec<u32>,
+    changed: alloc::vec::Vec<u32>,
+}
+
+impl Column {
+    fn push_item(&mut self) {
+        self.storage.push_item();
+        self.added.push(0);
+        self.changed.push(0);
+    }
+
+    // Column must be stored under key TypeId::of::<T>()
+    fn storage<T: 'static>(&self) -> &dyn Storage<Item = T> {
+        self.storage
+            .downcast_ref::<alloc::boxed::Box<dyn Storage<Item = T>>>()
+            .as_ref()
+    }
+
+    // Column must be stored under key TypeId::of::<T>()
+    fn storage_mut<T: 'static>(&mut self) -> &mut dyn Storage<Item = T> {
+        self.storage
+            .downcast_mut::<alloc::boxed::Box<dyn Storage<Item = T>>>()
+            .as_mut()
+    }
+
+    // Column must be stored under key TypeId::of::<T>().
+    // Pointer is derived without borrowing the storage, so pointers of query
+    // and filters accessing the same column do not invalidate each other.
+    fn storage_ptr<T: 'static>(&mut self) -> *mut dyn Storage<Item = T> {
+        let storage: *mut alloc::boxed::Box<dyn Storage<Item = T>> =
+            core::ptr::from_mut::<dyn AnyStorage>(&mut *self.storage).cast();
+        unsafe { &raw mut **storage }
     }
 }
 
@@ -87,56 +695,584 @@ impl Data {
     pub fn new() -> Self {
         Self::default()
     }
+}
 
-    /// Add new entity to the system
+impl<R: RefCount> Data<R> {
+    /// Add new entity to the system, reusing slot of despawned entity if there is one
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn entity(&mut self) -> Entity {
-        let id = self.rc.len();
-        self.rc.push(1);
+        self.flush_entities();
+        if let Some(index) = self.free.pop() {
+            let i = usize::try_from(index).unwrap();
+            self.rc[i] = R::ONE;
+            return Entity {
+                index,
+                generation: self.generations[i],
+            };
+        }
+        Entity {
+            index: self.push_slot(R::ONE),
+            generation: 0,
+        }
+    }
+
+    // Add new entity slot with given reference count, returns its index
+    fn push_slot(&mut self, rc: R) -> u32 {
+        let index = u32::try_from(self.rc.len()).unwrap();
+        self.rc.push(rc);
+        self.generations.push(0);
         for component in self.components.values_mut() {
             component.push_item();
         }
-        Entity(u32::try_from(id).unwrap())
+        index
     }
 
-    /// Add component to existing entity
-    #[allow(clippy::missing_panics_doc)]
-    pub fn insert<T: Default + 'static>(&mut self, entity: Entity, component: T) -> bool {
-        if let alloc::collections::btree_map::Entry::Vacant(e) =
-            self.components.entry(TypeId::of::<T>())
-        {
-            e.insert(alloc::boxed::Box::new(alloc::vec![component]));
-            false
+    /// Check whether entity was not despawned yet.
+    /// Reserved entities are not alive until data is flushed, see [`Data::reserve_entity`].
+    #[must_use]
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.i()) == Some(&entity.generation)
+            && self.rc[entity.i()] != R::default()
+    }
+
+    #[track_caller]
+    fn index(&self, entity: Entity) -> usize {
+        assert!(self.is_alive(entity), "{entity:?} is not alive");
+        entity.i()
+    }
+
+    /// Remove entity from the system, dropping all of its components.
+    /// Slot of the entity is reused by next call to [`Data::entity`].
+    /// On remove hooks of its components run before they are removed, see [`Data::on_remove`].
+    /// Returns false if entity was already despawned, even if its slot was reused since.
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// let mut world = Data::new();
+    /// let bullet = world.entity();
+    /// assert!(world.despawn(bullet));
+    /// assert!(!world.despawn(bullet));
+    ///
+    /// let projectile = world.entity();
+    /// assert_eq!(projectile.i(), bullet.i());
+    /// assert!(!world.despawn(bullet));
+    /// assert!(world.is_alive(projectile));
+    /// ```
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.run_remove_hooks(entity, None);
+        let i = entity.i();
+        self.rc[i] = R::default();
+        self.generations[i] = self.generations[i].wrapping_add(1);
+        for component in self.components.values_mut() {
+            component.storage.remove_item(entity);
+        }
+        self.free.push(entity.index);
+        true
+    }
+
+    /// Choose storage of component type.
+    /// Components that are inserted without being registered are stored in [`Slots<T>`].
+    /// Returns false if component type already has storage.
+    ///
+    /// ```
+    /// use ecs::{Data, Entity};
+    /// use std::collections::BTreeMap;
+    ///
+    /// struct OnFire(u32);
+    ///
+    /// let mut world = Data::new();
+    /// world.register::<BTreeMap<Entity, Box<OnFire>>>();
+    /// let torch = world.entity();
+    /// let rock = world.entity();
+    /// let bonfire = world.entity();
+    /// world.insert(torch, OnFire(1));
+    /// world.insert(bonfire, OnFire(5));
+    /// assert_eq!(world.storage::<BTreeMap<Entity, Box<OnFire>>>().unwrap().len(), 2);
+    ///
+    /// let mut fires: Vec<_> = world.query::<&mut OnFire>().collect();
+    /// fires[1].0 += fires[0].0;
+    /// fires[0].0 = 0;
+    /// assert_eq!(world.get::<OnFire>(bonfire).unwrap().0, 6);
+    ///
+    /// world.despawn(torch);
+    /// world.despawn(bonfire);
+    /// assert!(world.storage::<BTreeMap<Entity, Box<OnFire>>>().unwrap().is_empty());
+    /// ```
+    pub fn register<S: Storage + Default>(&mut self) -> bool {
+        let alloc::collections::btree_map::Entry::Vacant(e) =
+            self.components.entry(TypeId::of::<S::Item>())
+        else {
+            return false;
+        };
+        let mut storage = S::default();
+        for _ in 0..self.rc.len() {
+            storage.push_item();
+        }
+        let storage: alloc::boxed::Box<dyn Storage<Item = S::Item>> =
+            alloc::boxed::Box::new(storage);
+        e.insert(Column {
+            storage: alloc::boxed::Box::new(storage),
+            added: alloc::vec![0; self.rc.len()],
+            changed: alloc::vec![0; self.rc.len()],
+        });
+        true
+    }
+
+    /// Add component to existing entity, then run its hooks, see [`Data::on_add`] and [`Data::on_insert`].
+    /// Returns true if entity already had the component and it was overwritten.
+    ///
+    /// First insert of component type creates storage for all existing entities:
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Score(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let entities: Vec<_> = (0..5).map(|_| world.entity()).collect();
+    /// world.insert(entities[3], Score(3));
+    /// assert!(world.contains::<Score>(entities[3]));
+    /// assert!(!world.contains::<Score>(entities[0]));
+    ///
+    /// world.insert(entities[0], Score(0));
+    /// assert!(world.contains::<Score>(entities[0]));
+    ///
+    /// let late = world.entity();
+    /// assert!(!world.insert(late, Score(5)));
+    /// assert!(world.insert(late, Score(6)));
+    /// assert_eq!(world.remove::<Score>(late), Some(Score(6)));
+    /// assert_eq!(world.remove::<Score>(entities[0]), Some(Score(0)));
+    /// assert_eq!(world.remove::<Score>(entities[3]), Some(Score(3)));
+    /// ```
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> bool {
+        let i = self.index(entity);
+        self.register::<Slots<T>>();
+        let column = self.components.get_mut(&TypeId::of::<T>()).unwrap();
+        let replaced = column
+            .storage_mut::<T>()
+            .insert_item(entity, component)
+            .is_some();
+        if !replaced {
+            column.added[i] = self.tick;
+        }
+        column.changed[i] = self.tick;
+        self.run_add_hooks::<T>(entity, !replaced);
+        replaced
+    }
+
+    /// Remove component from entity, returns None if entity did not have the component.
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Stunned(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let enemy = world.entity();
+    /// world.insert(enemy, Stunned(3));
+    /// assert!(world.contains::<Stunned>(enemy));
+    /// assert_eq!(world.remove::<Stunned>(enemy), Some(Stunned(3)));
+    /// assert!(!world.contains::<Stunned>(enemy));
+    /// assert_eq!(world.remove::<Stunned>(enemy), None);
+    /// ```
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.index(entity);
+        self.run_remove_hooks(entity, Some(TypeId::of::<T>()));
+        self.column_mut::<T>()?.remove_item(entity)
+    }
+
+    /// Get component of entity
+    ///
+    /// ```
+    /// use ecs::{Data, EcsError};
+    ///
+    /// struct Health(u32);
+    /// struct Armor(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let player = world.entity();
+    /// world.insert(player, Health(100));
+    /// let rock = world.entity();
+    ///
+    /// assert_eq!(world.get::<Health>(player).unwrap().0, 100);
+    /// assert!(matches!(world.get::<Health>(rock), Err(EcsError::MissingComponent(..))));
+    /// assert!(matches!(world.get::<Armor>(player), Err(EcsError::UnknownComponent(_))));
+    /// world.despawn(player);
+    /// assert_eq!(world.get::<Health>(player).err(), Some(EcsError::DeadEntity(player)));
+    /// ```
+    /// # Errors
+    /// Returns error if entity is not alive, component type has no storage or entity does not have the component.
+    pub fn get<T: 'static>(&self, entity: Entity) -> Result<&T, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        let name = core::any::type_name::<T>();
+        self.column::<T>()
+            .ok_or(EcsError::UnknownComponent(name))?
+            .get_item(entity)
+            .ok_or(EcsError::MissingComponent(entity, name))
+    }
+
+    /// Mutably get component of entity
+    /// # Errors
+    /// Returns error if entity is not alive, component type has no storage or entity does not have the component.
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Result<&mut T, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        let name = core::any::type_name::<T>();
+        let column = self
+            .components
+            .get_mut(&TypeId::of::<T>())
+            .ok_or(EcsError::UnknownComponent(name))?;
+        // Borrow storage field alone, so that changed ticks can be stamped
+        let component = column
+            .storage
+            .downcast_mut::<alloc::boxed::Box<dyn Storage<Item = T>>>()
+            .get_item_mut(entity)
+            .ok_or(EcsError::MissingComponent(entity, name))?;
+        column.changed[entity.i()] = self.tick;
+        Ok(component)
+    }
+
+    /// Check whether entity is alive and has component T
+    #[must_use]
+    pub fn contains<T: 'static>(&self, entity: Entity) -> bool {
+        self.is_alive(entity)
+            && self
+                .column::<T>()
+                .is_some_and(|storage| storage.get_item(entity).is_some())
+    }
+
+    /// Get storage of component type, if it was registered as S
+    #[must_use]
+    pub fn storage<S: Storage>(&self) -> Option<&S> {
+        let storage: &dyn core::any::Any = self.column::<S::Item>()?;
+        storage.downcast_ref()
+    }
+
+    /// Mutably get storage of component type, if it was registered as S.
+    /// All components of the type are marked as changed.
+    #[must_use]
+    pub fn storage_mut<S: Storage>(&mut self) -> Option<&mut S> {
+        self.storage_changed::<S::Item, S>()
+    }
+
+    /// Get all values of single component type, if it is stored in [`Dense<T>`].
+    /// Entities without the component have `T::default()`, use [`Data::contains`] to tell them apart.
+    /// Component types are stored in [`Slots<T>`] unless they were registered before first insert,
+    /// so slice access requires `register::<Dense<T>>()`, see [`Data::register`].
+    ///
+    /// ```
+    /// use ecs::{Data, Dense};
+    ///
+    /// #[derive(Default)]
+    /// struct Mass(u32);
+    /// struct Name(&'static str);
+    ///
+    /// let mut world = Data::new();
+    /// world.register::<Dense<Mass>>();
+    /// let ball = world.entity();
+    /// world.insert(ball, Mass(3));
+    /// world.insert(ball, Name("ball"));
+    /// assert_eq!(world.values::<Mass>().unwrap()[ball.i()].0, 3);
+    /// assert!(world.values::<Name>().is_none());
+    /// ```
+    #[must_use]
+    pub fn values<T: 'static>(&self) -> Option<&[T]> {
+        let storage: &dyn core::any::Any = self.column::<T>()?;
+        storage.downcast_ref::<Dense<T>>().map(Dense::values)
+    }
+
+    /// Mutably get all values of single component type, if it is stored in [`Dense<T>`], see [`Data::values`].
+    /// All components of the type are marked as changed.
+    #[must_use]
+    pub fn values_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
+        self.storage_changed::<T, Dense<T>>().map(Dense::values_mut)
+    }
+
+    /// Mutably get values of several distinct component types at once, see [`Data::values_mut`].
+    ///
+    /// ```
+    /// use ecs::{Changed, Data, Dense, EcsError};
+    ///
+    /// #[derive(Default)]
+    /// struct Position(f64);
+    /// #[derive(Default)]
+    /// struct Velocity(f64);
+    ///
+    /// let mut world = Data::new();
+    /// world.register::<Dense<Position>>();
+    /// world.register::<Dense<Velocity>>();
+    /// let ball = world.entity();
+    /// world.insert(ball, Velocity(2.));
+    ///
+    /// let (positions, velocities) = world.values_many_mut::<(Position, Velocity)>().unwrap();
+    /// for (position, velocity) in positions.iter_mut().zip(velocities.iter_mut()) {
+    ///     position.0 += velocity.0;
+    ///     velocity.0 *= 0.5;
+    /// }
+    /// assert_eq!(world.values::<Position>().unwrap()[ball.i()].0, 2.);
+    ///
+    /// assert!(matches!(
+    ///     world.values_many_mut::<(Position, Position)>(),
+    ///     Err(EcsError::AliasedComponent(_))
+    /// ));
+    ///
+    /// // Failed access does not mark components as changed
+    /// world.insert(ball, 1u32);
+    /// let since = world.advance_tick();
+    /// assert!(matches!(
+    ///     world.values_many_mut::<(Position, u32)>(),
+    ///     Err(EcsError::NotDense(_))
+    /// ));
+    /// assert_eq!(world.query_since::<&Position, Changed<Position>>(since).count(), 0);
+    /// ```
+    /// # Errors
+    /// Returns error if component type is repeated, has no storage or is not stored in [`Dense<T>`].
+    pub fn values_many_mut<C: Components>(&mut self) -> Result<C::ValuesMut<'_>, EcsError> {
+        C::values_many_mut(self)
+    }
+
+    // Check that component type is stored in Dense<T>
+    fn check_dense<T: 'static>(&self) -> Result<(), EcsError> {
+        let name = core::any::type_name::<T>();
+        let storage: &dyn core::any::Any =
+            self.column::<T>().ok_or(EcsError::UnknownComponent(name))?;
+        if storage.is::<Dense<T>>() {
+            Ok(())
         } else {
-            self.query_mut::<T>().unwrap()[entity.i()] = component;
-            true
+            Err(EcsError::NotDense(name))
         }
     }
 
-    /// Query all values of single component type
+    // Storage must be checked with check_dense first
+    fn dense_mut<T: 'static>(&mut self) -> &mut Dense<T> {
+        self.storage_changed::<T, Dense<T>>().unwrap()
+    }
+
+    /// Current tick, components are stamped with it when they are added or mutably accessed
     #[must_use]
-    pub fn query<T: 'static>(&self) -> Option<&[T]> {
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Advance current tick and return it.
+    /// Systems can remember returned tick after they run and query changes since then,
+    /// see [`Data::query_since`].
+    pub fn advance_tick(&mut self) -> u32 {
+        self.tick = self.tick.wrapping_add(1);
+        self.tick
+    }
+
+    /// Insert resource, which is single value of its type not attached to any entity.
+    /// Returns previous value of the resource.
+    ///
+    /// ```
+    /// use ecs::{Data, EcsError};
+    ///
+    /// struct Time(f64);
+    /// struct Gravity(f64);
+    ///
+    /// let mut world = Data::new();
+    /// assert!(world.insert_resource(Time(0.)).is_none());
+    /// world.resource_mut::<Time>().unwrap().0 += 0.5;
+    /// assert_eq!(world.resource::<Time>().unwrap().0, 0.5);
+    /// assert_eq!(world.insert_resource(Time(1.)).unwrap().0, 0.5);
+    /// assert!(matches!(world.resource::<Gravity>(), Err(EcsError::MissingResource(_))));
+    /// assert_eq!(world.remove_resource::<Time>().unwrap().0, 1.);
+    /// ```
+    #[allow(clippy::missing_panics_doc)]
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), alloc::boxed::Box::new(resource))
+            .map(|previous| *previous.downcast().unwrap())
+    }
+
+    /// Remove resource, returns None if it was not inserted
+    #[allow(clippy::missing_panics_doc)]
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|resource| *resource.downcast().unwrap())
+    }
+
+    /// Get resource
+    /// # Errors
+    /// Returns error if resource was not inserted.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn resource<T: 'static>(&self) -> Result<&T, EcsError> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .map(|resource| resource.downcast_ref().unwrap())
+            .ok_or(EcsError::MissingResource(core::any::type_name::<T>()))
+    }
+
+    /// Mutably get resource
+    /// # Errors
+    /// Returns error if resource was not inserted.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn resource_mut<T: 'static>(&mut self) -> Result<&mut T, EcsError> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .map(|resource| resource.downcast_mut().unwrap())
+            .ok_or(EcsError::MissingResource(core::any::type_name::<T>()))
+    }
+
+    fn column<T: 'static>(&self) -> Option<&dyn Storage<Item = T>> {
         self.components
             .get(&TypeId::of::<T>())
-            .map(|x| x.downcast_ref::<alloc::vec::Vec<T>>().as_ref())
+            .map(Column::storage::<T>)
     }
 
-    /// Mutably query all values of single component type
-    #[must_use]
-    pub fn query_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
+    // Mutably get storage of component type T if it is S and mark all of its components as changed
+    fn storage_changed<T: 'static, S: 'static>(&mut self) -> Option<&mut S> {
+        let column = self.components.get_mut(&TypeId::of::<T>())?;
+        let storage: &mut dyn core::any::Any = column
+            .storage
+            .downcast_mut::<alloc::boxed::Box<dyn Storage<Item = T>>>()
+            .as_mut();
+        // Components are stamped only if storage is S
+        let storage = storage.downcast_mut()?;
+        column.changed.fill(self.tick);
+        Some(storage)
+    }
+
+    fn column_mut<T: 'static>(&mut self) -> Option<&mut dyn Storage<Item = T>> {
         self.components
             .get_mut(&TypeId::of::<T>())
-            .map(|x| x.downcast_mut::<alloc::vec::Vec<T>>().as_mut())
+            .map(Column::storage_mut::<T>)
     }
 
     /// Increase reference count of single entity
+    /// # Panics
+    /// Panics if entity is not alive or if its reference count overflows.
+    #[track_caller]
     pub fn retain(&mut self, entity: Entity) {
-        self.rc[entity.i()] += 1;
+        if let Err(e) = self.try_retain(entity) {
+            panic!("{e}");
+        }
+    }
+
+    /// Increase reference count of single entity
+    /// # Errors
+    /// Returns error if entity is not alive or if its reference count would overflow.
+    ///
+    /// ```
+    /// use ecs::{Data, EcsError};
+    ///
+    /// let mut world = Data::<u8>::default();
+    /// let node = world.entity();
+    /// for _ in 0..254 {
+    ///     world.retain(node);
+    /// }
+    /// assert_eq!(world.try_retain(node), Err(EcsError::RefCountOverflow(node)));
+    ///
+    /// let mut world = Data::<u16>::default();
+    /// let node = world.entity();
+    /// for _ in 0..255 {
+    ///     world.retain(node);
+    /// }
+    /// assert_eq!(world.try_retain(node), Ok(()));
+    /// ```
+    pub fn try_retain(&mut self, entity: Entity) -> Result<(), EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        let rc = &mut self.rc[entity.i()];
+        *rc = rc.increment().ok_or(EcsError::RefCountOverflow(entity))?;
+        Ok(())
+    }
+
+    /// Decrease reference count of single entity.
+    /// Once reference count drops to zero, entity is despawned and true is returned.
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn release(&mut self, entity: Entity) -> bool {
+        self.release_with(entity, |_, _| {})
+    }
+
+    /// Decrease reference count of single entity.
+    /// Once reference count drops to zero, entity is despawned and true is returned.
+    /// # Errors
+    /// Returns error if entity is not alive.
+    pub fn try_release(&mut self, entity: Entity) -> Result<bool, EcsError> {
+        self.try_release_with(entity, |_, _| {})
     }
 
-    /// Decrease reference count of single entity
-    pub fn release(&mut self, entity: Entity) {
-        self.rc[entity.i()] -= 1;
+    /// Decrease reference count of single entity.
+    /// Once reference count drops to zero, `on_despawn` is called while components
+    /// of the entity are still accessible, then entity is despawned and true is returned.
+    ///
+    /// ```
+    /// use ecs::{Data, Entity};
+    ///
+    /// struct Parent(Entity);
+    ///
+    /// let mut world = Data::new();
+    /// let parent = world.entity();
+    /// let child = world.entity();
+    /// world.insert(child, Parent(parent));
+    ///
+    /// let release_parent = |world: &mut Data, entity: Entity| {
+    ///     if let Some(Parent(parent)) = world.remove::<Parent>(entity) {
+    ///         world.release(parent);
+    ///     }
+    /// };
+    /// assert!(world.release_with(child, release_parent));
+    /// assert!(!world.is_alive(child));
+    /// assert!(!world.is_alive(parent));
+    /// ```
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn release_with(
+        &mut self,
+        entity: Entity,
+        on_despawn: impl FnOnce(&mut Self, Entity),
+    ) -> bool {
+        match self.try_release_with(entity, on_despawn) {
+            Ok(despawned) => despawned,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Decrease reference count of single entity, see [`Data::release_with`].
+    /// # Errors
+    /// Returns error if entity is not alive.
+    pub fn try_release_with(
+        &mut self,
+        entity: Entity,
+        on_despawn: impl FnOnce(&mut Self, Entity),
+    ) -> Result<bool, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        let i = entity.i();
+        // Reference count of alive entity is never zero
+        let rc = self.rc[i].decrement().unwrap_or_default();
+        if rc != R::default() {
+            self.rc[i] = rc;
+            return Ok(false);
+        }
+        // Count is kept until despawn, so that entity is alive in on_despawn
+        on_despawn(self, entity);
+        self.despawn(entity);
+        Ok(true)
     }
 }
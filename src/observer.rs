@@ -0,0 +1,234 @@
+//! Observers of custom events.
+
+use crate::{Data, Entity, RefCount};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::any::{Any, TypeId};
+
+type Observer<R> = Box<dyn FnMut(&mut Data<R>, Option<Entity>, &dyn Any)>;
+type Event = (TypeId, Option<Entity>, Box<dyn Any>);
+
+// Observers of all event types together with events waiting for flush
+pub(crate) struct Observers<R: RefCount> {
+    // Observers of each event type, true for deferred observers
+    by_type: BTreeMap<TypeId, Vec<(bool, Observer<R>)>>,
+    // Event types whose observers are taken out of the map while they run
+    running: BTreeSet<TypeId>,
+    // Events triggered while observers of their type were running
+    pending: Vec<Event>,
+    deferred: Vec<Event>,
+}
+
+impl<R: RefCount> Default for Observers<R> {
+    fn default() -> Self {
+        Self {
+            by_type: BTreeMap::new(),
+            running: BTreeSet::new(),
+            pending: Vec::new(),
+            deferred: Vec::new(),
+        }
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Add observer of events of type E, which runs immediately when event is triggered.
+    /// Observer gets target entity, or None for global events.
+    /// Events of type E triggered by observers of E run observers of E once they finish.
+    ///
+    /// ```
+    /// use ecs::{Data, Entity};
+    ///
+    /// struct Health(u32);
+    /// struct Damage(u32);
+    /// struct Explosion(u32);
+    ///
+    /// let mut world = Data::new();
+    /// world.observe(|world: &mut Data, target: Option<Entity>, damage: &Damage| {
+    ///     let health = world.get_mut::<Health>(target.unwrap()).unwrap();
+    ///     health.0 = health.0.saturating_sub(damage.0);
+    /// });
+    /// world.observe(|world: &mut Data, _, explosion: &Explosion| {
+    ///     let targets: Vec<_> = world.query::<(Entity, &Health)>().map(|(entity, _)| entity).collect();
+    ///     for target in targets {
+    ///         world.trigger(target, Damage(explosion.0));
+    ///     }
+    /// });
+    ///
+    /// let player = world.entity();
+    /// world.insert(player, Health(100));
+    /// let enemy = world.entity();
+    /// world.insert(enemy, Health(20));
+    /// world.trigger(player, Damage(10));
+    /// world.trigger_global(Explosion(15));
+    /// assert_eq!(world.get::<Health>(player).unwrap().0, 75);
+    /// assert_eq!(world.get::<Health>(enemy).unwrap().0, 5);
+    /// ```
+    pub fn observe<E: 'static>(
+        &mut self,
+        observer: impl FnMut(&mut Self, Option<Entity>, &E) + 'static,
+    ) {
+        self.add_observer(false, observer);
+    }
+
+    /// Add observer of events of type E, which runs once events are flushed by [`Data::flush`].
+    /// Target entity may be despawned before flush.
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// struct Score(u32);
+    /// struct Scored(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let player = world.entity();
+    /// world.insert(player, Score(0));
+    /// world.observe_deferred(|world: &mut Data, target, scored: &Scored| {
+    ///     world.get_mut::<Score>(target.unwrap()).unwrap().0 += scored.0;
+    /// });
+    ///
+    /// world.trigger(player, Scored(3));
+    /// world.trigger(player, Scored(4));
+    /// assert_eq!(world.get::<Score>(player).unwrap().0, 0);
+    /// world.flush();
+    /// assert_eq!(world.get::<Score>(player).unwrap().0, 7);
+    /// ```
+    pub fn observe_deferred<E: 'static>(
+        &mut self,
+        observer: impl FnMut(&mut Self, Option<Entity>, &E) + 'static,
+    ) {
+        self.add_observer(true, observer);
+    }
+
+    /// Trigger event at entity, running its immediate observers and queueing it for deferred observers
+    /// # Panics
+    /// Panics if entity is not alive.
+    #[track_caller]
+    pub fn trigger<E: 'static>(&mut self, entity: Entity, event: E) {
+        self.index(entity);
+        self.trigger_event(Some(entity), event);
+    }
+
+    /// Trigger event without target entity, running its immediate observers and queueing it for deferred observers
+    pub fn trigger_global<E: 'static>(&mut self, event: E) {
+        self.trigger_event(None, event);
+    }
+
+    /// Spawn entities reserved by [`Data::reserve_entity`], then run deferred observers
+    /// of all events triggered since last flush, in order in which they were triggered.
+    /// Events triggered by deferred observers run immediate observers right away
+    /// and wait for next flush for deferred observers.
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// struct Ping(u32);
+    /// struct Deferred(Vec<u32>);
+    /// struct Immediate(Vec<u32>);
+    ///
+    /// let mut world = Data::new();
+    /// world.insert_resource(Deferred(Vec::new()));
+    /// world.insert_resource(Immediate(Vec::new()));
+    /// world.observe_deferred(|world: &mut Data, _, ping: &Ping| {
+    ///     world.resource_mut::<Deferred>().unwrap().0.push(ping.0);
+    ///     if ping.0 > 0 {
+    ///         world.trigger_global(Ping(ping.0 - 1));
+    ///     }
+    /// });
+    /// world.observe(|world: &mut Data, _, ping: &Ping| {
+    ///     world.resource_mut::<Immediate>().unwrap().0.push(ping.0);
+    ///     if ping.0 == 5 {
+    ///         world.trigger_global(Ping(2));
+    ///     }
+    /// });
+    ///
+    /// world.trigger_global(Ping(5));
+    /// assert_eq!(world.resource::<Immediate>().unwrap().0, [5, 2]);
+    /// world.flush();
+    /// assert_eq!(world.resource::<Deferred>().unwrap().0, [5, 2]);
+    /// assert_eq!(world.resource::<Immediate>().unwrap().0, [5, 2, 4, 1]);
+    /// world.flush();
+    /// assert_eq!(world.resource::<Deferred>().unwrap().0, [5, 2, 4, 1]);
+    /// assert_eq!(world.resource::<Immediate>().unwrap().0, [5, 2, 4, 1, 3, 0]);
+    /// ```
+    pub fn flush(&mut self) {
+        self.flush_entities();
+        for (id, target, event) in core::mem::take(&mut self.observers.deferred) {
+            self.run_observers(id, true, target, event.as_ref());
+        }
+    }
+
+    fn add_observer<E: 'static>(
+        &mut self,
+        deferred: bool,
+        mut observer: impl FnMut(&mut Self, Option<Entity>, &E) + 'static,
+    ) {
+        let observer: Observer<R> = Box::new(move |data, target, event| {
+            // Observers are stored under key TypeId::of::<E>()
+            observer(data, target, event.downcast_ref().unwrap());
+        });
+        self.observers
+            .by_type
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push((deferred, observer));
+    }
+
+    fn trigger_event<E: 'static>(&mut self, target: Option<Entity>, event: E) {
+        self.dispatch((TypeId::of::<E>(), target, Box::new(event)));
+    }
+
+    // Run immediate observers of event and queue it for deferred observers
+    fn dispatch(&mut self, (id, target, event): Event) {
+        if self.observers.running.contains(&id) {
+            self.observers.pending.push((id, target, event));
+            return;
+        }
+        // Events triggered by immediate observers are queued after this one
+        let position = self.observers.deferred.len();
+        self.run_observers(id, false, target, event.as_ref());
+        let has_deferred = self
+            .observers
+            .by_type
+            .get(&id)
+            .is_some_and(|observers| observers.iter().any(|(deferred, _)| *deferred));
+        if has_deferred {
+            let position = position.min(self.observers.deferred.len());
+            self.observers
+                .deferred
+                .insert(position, (id, target, event));
+        }
+    }
+
+    // Observers of event type are taken out of data while they run,
+    // events of the type triggered meanwhile are dispatched once they finish
+    fn run_observers(
+        &mut self,
+        id: TypeId,
+        deferred: bool,
+        target: Option<Entity>,
+        event: &dyn Any,
+    ) {
+        let Some(mut observers) = self.observers.by_type.remove(&id) else {
+            return;
+        };
+        self.observers.running.insert(id);
+        for (_, observer) in observers.iter_mut().filter(|(d, _)| *d == deferred) {
+            observer(self, target, event);
+        }
+        self.observers.running.remove(&id);
+        // Keep observers added while running after existing ones
+        if let Some(added) = self.observers.by_type.insert(id, observers) {
+            self.observers.by_type.get_mut(&id).unwrap().extend(added);
+        }
+        let (pending, rest): (Vec<_>, _) = core::mem::take(&mut self.observers.pending)
+            .into_iter()
+            .partition(|(pending, _, _)| *pending == id);
+        self.observers.pending = rest;
+        for event in pending {
+            self.dispatch(event);
+        }
+    }
+}
@@ -0,0 +1,664 @@
+//! Queries joining multiple component types.
+
+use crate::{Data, Dense, EcsError, Entity, RefCount, Storage, Table};
+use alloc::vec::Vec;
+use core::{any::TypeId, marker::PhantomData};
+
+/// Query of components
+///
+/// Implemented for `&T`, `&mut T`, [`Entity`], which yields entity itself,
+/// `Option` of query, which matches entities with and without the components, and tuples of queries.
+/// # Safety
+/// [`Query::access`] must append every component type whose storage is accessed by
+/// [`Query::fetch`], [`Query::get`] and [`Query::stamp`], marked as mutable if components are mutably borrowed,
+/// because queries are checked for aliasing mutable access by it alone.
+pub unsafe trait Query {
+    /// Item yielded for each entity matching the query
+    type Item<'a>;
+    /// Pointers to storages accessed by the query
+    type Fetch: Copy;
+
+    /// Append component types accessed by the query, true for mutable access
+    fn access(access: &mut Vec<(TypeId, bool)>);
+
+    /// Get pointers to storages, None if some storage does not exist
+    fn fetch<R: RefCount>(data: &mut Data<R>) -> Option<Self::Fetch>;
+
+    /// Get item of entity, None if entity does not match the query
+    /// # Safety
+    /// Storages in fetch must outlive 'a and no other reference to returned components may exist.
+    unsafe fn get<'a>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'a>>;
+
+    /// Mark mutably borrowed components of entity as changed, called once item of entity was yielded
+    /// # Safety
+    /// Storages in fetch must be alive.
+    unsafe fn stamp(fetch: Self::Fetch, entity: Entity);
+}
+
+/// Query that only reads components, so it can run on shared reference to data
+///
+/// Implemented for `&T`, [`Entity`], `Option` of read-only query and tuples of read-only queries.
+/// # Safety
+/// [`Query::access`] must mark no access as mutable, [`Query::get`] must not mutate components
+/// and [`Query::stamp`] must do nothing.
+pub unsafe trait ReadOnlyQuery: Query {
+    /// Get pointers to storages from shared reference, None if some storage does not exist
+    fn fetch_ref<R: RefCount>(data: &Data<R>) -> Option<Self::Fetch>;
+}
+
+unsafe impl<T: 'static> Query for &T {
+    type Item<'a> = &'a T;
+    type Fetch = *const dyn Storage<Item = T>;
+
+    fn access(access: &mut Vec<(TypeId, bool)>) {
+        access.push((TypeId::of::<T>(), false));
+    }
+
+    fn fetch<R: RefCount>(data: &mut Data<R>) -> Option<Self::Fetch> {
+        let column = data.components.get_mut(&TypeId::of::<T>())?;
+        Some(column.storage_ptr::<T>().cast_const())
+    }
+
+    unsafe fn get<'a>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'a>> {
+        unsafe { (*fetch).get_item(entity) }
+    }
+
+    unsafe fn stamp(_: Self::Fetch, _: Entity) {}
+}
+
+unsafe impl<T: 'static> ReadOnlyQuery for &T {
+    fn fetch_ref<R: RefCount>(data: &Data<R>) -> Option<Self::Fetch> {
+        let column = data.components.get(&TypeId::of::<T>())?;
+        Some(core::ptr::from_ref(column.storage::<T>()))
+    }
+}
+
+unsafe impl<T: 'static> Query for &mut T {
+    type Item<'a> = &'a mut T;
+    // Storage, changed ticks and current tick
+    type Fetch = (*mut dyn Storage<Item = T>, *mut u32, u32);
+
+    fn access(access: &mut Vec<(TypeId, bool)>) {
+        access.push((TypeId::of::<T>(), true));
+    }
+
+    fn fetch<R: RefCount>(data: &mut Data<R>) -> Option<Self::Fetch> {
+        let tick = data.tick;
+        let column = data.components.get_mut(&TypeId::of::<T>())?;
+        let changed = column.changed.as_mut_ptr();
+        Some((column.storage_ptr::<T>(), changed, tick))
+    }
+
+    unsafe fn get<'a>((storage, _, _): Self::Fetch, entity: Entity) -> Option<Self::Item<'a>> {
+        // Components of entities yielded earlier are not borrowed again
+        let component = unsafe { (*storage).get_item_ptr(entity) }?;
+        Some(unsafe { &mut *component })
+    }
+
+    unsafe fn stamp((storage, changed, tick): Self::Fetch, entity: Entity) {
+        if unsafe { (*storage).contains_item(entity) } {
+            // Ticks have one slot per entity
+            unsafe { *changed.add(entity.i()) = tick };
+        }
+    }
+}
+
+unsafe impl Query for Entity {
+    type Item<'a> = Entity;
+    type Fetch = ();
+
+    fn access(_: &mut Vec<(TypeId, bool)>) {}
+
+    fn fetch<R: RefCount>(_: &mut Data<R>) -> Option<Self::Fetch> {
+        Some(())
+    }
+
+    unsafe fn get<'a>((): Self::Fetch, entity: Entity) -> Option<Self::Item<'a>> {
+        Some(entity)
+    }
+
+    unsafe fn stamp((): Self::Fetch, _: Entity) {}
+}
+
+unsafe impl ReadOnlyQuery for Entity {
+    fn fetch_ref<R: RefCount>(_: &Data<R>) -> Option<Self::Fetch> {
+        Some(())
+    }
+}
+
+unsafe impl<Q: Query> Query for Option<Q> {
+    type Item<'a> = Option<Q::Item<'a>>;
+    type Fetch = Option<Q::Fetch>;
+
+    fn access(access: &mut Vec<(TypeId, bool)>) {
+        Q::access(access);
+    }
+
+    fn fetch<R: RefCount>(data: &mut Data<R>) -> Option<Self::Fetch> {
+        Some(Q::fetch(data))
+    }
+
+    unsafe fn get<'a>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'a>> {
+        Some(fetch.and_then(|fetch| unsafe { Q::get(fetch, entity) }))
+    }
+
+    unsafe fn stamp(fetch: Self::Fetch, entity: Entity) {
+        if let Some(fetch) = fetch {
+            unsafe { Q::stamp(fetch, entity) };
+        }
+    }
+}
+
+unsafe impl<Q: ReadOnlyQuery> ReadOnlyQuery for Option<Q> {
+    fn fetch_ref<R: RefCount>(data: &Data<R>) -> Option<Self::Fetch> {
+        Some(Q::fetch_ref(data))
+    }
+}
+
+macro_rules! impl_query {
+    ($($q:ident),*) => {
+        unsafe impl<$($q: Query),*> Query for ($($q,)*) {
+            type Item<'a> = ($($q::Item<'a>,)*);
+            type Fetch = ($($q::Fetch,)*);
+
+            fn access(access: &mut Vec<(TypeId, bool)>) {
+                $($q::access(access);)*
+            }
+
+            fn fetch<R: RefCount>(data: &mut Data<R>) -> Option<Self::Fetch> {
+                Some(($($q::fetch(data)?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn get<'a>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'a>> {
+                let ($($q,)*) = fetch;
+                Some(($(unsafe { $q::get($q, entity) }?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn stamp(fetch: Self::Fetch, entity: Entity) {
+                let ($($q,)*) = fetch;
+                $(unsafe { $q::stamp($q, entity) };)*
+            }
+        }
+
+        unsafe impl<$($q: ReadOnlyQuery),*> ReadOnlyQuery for ($($q,)*) {
+            fn fetch_ref<R: RefCount>(data: &Data<R>) -> Option<Self::Fetch> {
+                Some(($($q::fetch_ref(data)?,)*))
+            }
+        }
+    };
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+impl_query!(A, B, C, D, E);
+impl_query!(A, B, C, D, E, F);
+impl_query!(A, B, C, D, E, F, G);
+impl_query!(A, B, C, D, E, F, G, H);
+
+/// Filter of entities by presence or changes of components
+///
+/// Implemented for [`With`], [`Without`], [`Added`], [`Changed`], [`Or`]
+/// and tuples of filters, which match if all of them match.
+/// # Safety
+/// [`Filter::matches`] must not read components or create references to them,
+/// only presence of components and their ticks, because components may be mutably borrowed by query.
+pub unsafe trait Filter {
+    /// Pointers to storages read by the filter
+    type Fetch: Copy;
+
+    /// Get pointers to storages, changes are detected at ticks since given tick
+    fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch;
+
+    /// Check whether entity matches the filter
+    /// # Safety
+    /// Storages in fetch must be alive. Components are not read by filters,
+    /// so they may be mutably borrowed by query.
+    unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool;
+}
+
+/// Filter of entities that have component T
+pub struct With<T>(PhantomData<T>);
+
+/// Filter of entities that do not have component T
+pub struct Without<T>(PhantomData<T>);
+
+/// Filter of entities that match at least one filter of tuple F
+pub struct Or<F>(PhantomData<F>);
+
+/// Filter of entities whose component T was added since given tick
+pub struct Added<T>(PhantomData<T>);
+
+/// Filter of entities whose component T was added or mutably accessed since given tick
+pub struct Changed<T>(PhantomData<T>);
+
+unsafe impl<T: 'static> Filter for With<T> {
+    type Fetch = Option<*const dyn Storage<Item = T>>;
+
+    fn fetch<R: RefCount>(data: &mut Data<R>, _: u32) -> Self::Fetch {
+        let column = data.components.get_mut(&TypeId::of::<T>())?;
+        Some(column.storage_ptr::<T>().cast_const())
+    }
+
+    unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+        fetch.is_some_and(|storage| unsafe { (*storage).contains_item(entity) })
+    }
+}
+
+unsafe impl<T: 'static> Filter for Without<T> {
+    type Fetch = Option<*const dyn Storage<Item = T>>;
+
+    fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch {
+        With::<T>::fetch(data, since)
+    }
+
+    unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+        !unsafe { With::<T>::matches(fetch, entity) }
+    }
+}
+
+unsafe impl<T: 'static> Filter for Added<T> {
+    // Storage, added ticks, tick since which changes are detected and current tick
+    type Fetch = Option<(*const dyn Storage<Item = T>, *const u32, u32, u32)>;
+
+    fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch {
+        let column = data.components.get_mut(&TypeId::of::<T>())?;
+        // Derived like pointers of query, so that they do not invalidate each other
+        let added = column.added.as_mut_ptr().cast_const();
+        let storage = column.storage_ptr::<T>().cast_const();
+        Some((storage, added, since, data.tick))
+    }
+
+    unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+        fetch.is_some_and(|(storage, added, since, tick)| unsafe {
+            (*storage).contains_item(entity) && stamped_since(*added.add(entity.i()), since, tick)
+        })
+    }
+}
+
+unsafe impl<T: 'static> Filter for Changed<T> {
+    // Storage, changed ticks, tick since which changes are detected and current tick
+    type Fetch = Option<(*const dyn Storage<Item = T>, *const u32, u32, u32)>;
+
+    fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch {
+        let column = data.components.get_mut(&TypeId::of::<T>())?;
+        let changed = column.changed.as_mut_ptr().cast_const();
+        let storage = column.storage_ptr::<T>().cast_const();
+        Some((storage, changed, since, data.tick))
+    }
+
+    unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+        fetch.is_some_and(|(storage, changed, since, tick)| unsafe {
+            (*storage).contains_item(entity) && stamped_since(*changed.add(entity.i()), since, tick)
+        })
+    }
+}
+
+// Check whether component was stamped at tick since or later.
+// Ticks wrap around, so ages relative to current tick are compared.
+fn stamped_since(stamp: u32, since: u32, tick: u32) -> bool {
+    tick.wrapping_sub(stamp) <= tick.wrapping_sub(since)
+}
+
+unsafe impl Filter for () {
+    type Fetch = ();
+
+    fn fetch<R: RefCount>(_: &mut Data<R>, _: u32) -> Self::Fetch {}
+
+    unsafe fn matches((): Self::Fetch, _: Entity) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_filter {
+    ($($f:ident),*) => {
+        unsafe impl<$($f: Filter),*> Filter for ($($f,)*) {
+            type Fetch = ($($f::Fetch,)*);
+
+            fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch {
+                ($($f::fetch(data, since),)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+                let ($($f,)*) = fetch;
+                true $(&& unsafe { $f::matches($f, entity) })*
+            }
+        }
+
+        unsafe impl<$($f: Filter),*> Filter for Or<($($f,)*)> {
+            type Fetch = ($($f::Fetch,)*);
+
+            fn fetch<R: RefCount>(data: &mut Data<R>, since: u32) -> Self::Fetch {
+                ($($f::fetch(data, since),)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn matches(fetch: Self::Fetch, entity: Entity) -> bool {
+                let ($($f,)*) = fetch;
+                false $(|| unsafe { $f::matches($f, entity) })*
+            }
+        }
+    };
+}
+
+impl_filter!(A);
+impl_filter!(A, B);
+impl_filter!(A, B, C);
+impl_filter!(A, B, C, D);
+impl_filter!(A, B, C, D, E);
+impl_filter!(A, B, C, D, E, F);
+impl_filter!(A, B, C, D, E, F, G);
+impl_filter!(A, B, C, D, E, F, G, H);
+
+/// Tuple of distinct component types, whose values can be mutably borrowed at once
+pub trait Components {
+    /// Tuple of mutable slices of values of each component type
+    type ValuesMut<'a>;
+
+    /// Mutably borrow values of each component type
+    /// # Errors
+    /// Returns error if component type is repeated, has no storage or is not stored in [`Dense`].
+    fn values_many_mut<R: RefCount>(data: &mut Data<R>) -> Result<Self::ValuesMut<'_>, EcsError>;
+
+    /// Mutably borrow column of each component type in table
+    /// # Errors
+    /// Returns error if component type is repeated or entities in table do not have it.
+    fn columns_mut(table: &mut Table) -> Result<Self::ValuesMut<'_>, EcsError>;
+}
+
+// Check that component types are distinct
+fn check_distinct(ids: &[(TypeId, &'static str)]) -> Result<(), EcsError> {
+    for (i, (id, name)) in ids.iter().enumerate() {
+        if ids[..i].iter().any(|(other, _)| other == id) {
+            return Err(EcsError::AliasedComponent(name));
+        }
+    }
+    Ok(())
+}
+
+macro_rules! impl_components {
+    ($($t:ident),*) => {
+        impl<$($t: 'static),*> Components for ($($t,)*) {
+            type ValuesMut<'a> = ($(&'a mut [$t],)*);
+
+            #[allow(non_snake_case)]
+            fn values_many_mut<R: RefCount>(
+                data: &mut Data<R>,
+            ) -> Result<Self::ValuesMut<'_>, EcsError> {
+                check_distinct(&[$((TypeId::of::<$t>(), core::any::type_name::<$t>())),*])?;
+                // Components are stamped as changed only if all storages are Dense
+                $(data.check_dense::<$t>()?;)*
+                $(let $t: *mut Dense<$t> = data.dense_mut::<$t>();)*
+                // Component types are distinct, so storages do not alias
+                Ok(($(unsafe { (*$t).values_mut() },)*))
+            }
+
+            #[allow(non_snake_case)]
+            fn columns_mut(table: &mut Table) -> Result<Self::ValuesMut<'_>, EcsError> {
+                check_distinct(&[$((TypeId::of::<$t>(), core::any::type_name::<$t>())),*])?;
+                $(let $t = table
+                    .column_ptr::<$t>()
+                    .ok_or(EcsError::UnknownComponent(core::any::type_name::<$t>()))?;)*
+                // Component types are distinct, so columns do not alias
+                Ok(($(unsafe { (*$t).as_mut_slice() },)*))
+            }
+        }
+    };
+}
+
+impl_components!(A);
+impl_components!(A, B);
+impl_components!(A, B, C);
+impl_components!(A, B, C, D);
+impl_components!(A, B, C, D, E);
+impl_components!(A, B, C, D, E, F);
+impl_components!(A, B, C, D, E, F, G);
+impl_components!(A, B, C, D, E, F, G, H);
+
+/// Iterator over components of entities matching query Q and filter F
+pub struct QueryIter<'a, Q: Query, F: Filter = (), R: RefCount = u8> {
+    fetch: Option<Q::Fetch>,
+    filter: F::Fetch,
+    rc: &'a [R],
+    generations: &'a [u32],
+    index: usize,
+    marker: PhantomData<&'a mut Q>,
+}
+
+impl<'a, Q: Query, F: Filter, R: RefCount> Iterator for QueryIter<'a, Q, F, R> {
+    type Item = Q::Item<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch = self.fetch?;
+        while let Some(&generation) = self.generations.get(self.index) {
+            let entity = Entity {
+                index: u32::try_from(self.index).unwrap(),
+                generation,
+            };
+            self.index += 1;
+            if self.rc[entity.i()] == R::default() {
+                continue;
+            }
+            // Access was checked when iterator was created and each entity is visited once
+            if !unsafe { F::matches(self.filter, entity) } {
+                continue;
+            }
+            if let Some(item) = unsafe { Q::get(fetch, entity) } {
+                // Components are stamped only once all terms of query matched
+                unsafe { Q::stamp(fetch, entity) };
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Query components of all entities that have all of them.
+    /// Despawned entities and entities with zero reference count are skipped.
+    ///
+    /// ```
+    /// use ecs::{Data, Entity};
+    ///
+    /// struct Position(f64, f64);
+    /// struct Velocity(f64, f64);
+    ///
+    /// let mut world = Data::new();
+    /// let player = world.entity();
+    /// world.insert(player, Position(0., 0.));
+    /// world.insert(player, Velocity(1., 2.));
+    /// let rock = world.entity();
+    /// world.insert(rock, Position(5., 5.));
+    ///
+    /// for (position, velocity) in world.query::<(&mut Position, &Velocity)>() {
+    ///     position.0 += velocity.0;
+    ///     position.1 += velocity.1;
+    /// }
+    /// let positions: Vec<_> = world.query::<&Position>().map(|p| (p.0, p.1)).collect();
+    /// assert_eq!(positions, [(1., 2.), (5., 5.)]);
+    ///
+    /// // Components of different entities can be borrowed at once
+    /// let mut positions = world.query::<&mut Position>();
+    /// let first = positions.next().unwrap();
+    /// let second = positions.next().unwrap();
+    /// std::mem::swap(first, second);
+    /// first.0 += 1.;
+    /// assert_eq!((first.0, second.1), (6., 2.));
+    ///
+    /// let moving: Vec<_> = world
+    ///     .query::<(&Position, Option<&Velocity>)>()
+    ///     .map(|(_, velocity)| velocity.is_some())
+    ///     .collect();
+    /// assert_eq!(moving, [true, false]);
+    ///
+    /// world.despawn(rock);
+    /// let entities: Vec<_> = world.query::<(Entity, &Position)>().map(|(entity, _)| entity).collect();
+    /// assert_eq!(entities, [player]);
+    /// ```
+    /// # Panics
+    /// Panics if query accesses the same component type more than once and at least one access is mutable.
+    #[track_caller]
+    pub fn query<Q: Query>(&mut self) -> QueryIter<'_, Q, (), R> {
+        self.query_filtered()
+    }
+
+    /// Query components of all entities that have all of them without mutably borrowing data,
+    /// see [`Data::query`].
+    ///
+    /// ```
+    /// use ecs::{Data, Entity};
+    ///
+    /// struct Name(&'static str);
+    /// struct Health(u32);
+    ///
+    /// let mut world = Data::new();
+    /// let player = world.entity();
+    /// world.insert(player, Name("player"));
+    /// world.insert(player, Health(10));
+    /// let sign = world.entity();
+    /// world.insert(sign, Name("sign"));
+    ///
+    /// let world = &world;
+    /// let names = world.query_ref::<(&Name, Option<&Health>)>();
+    /// let healthy = world.query_ref::<(Entity, &Health)>();
+    /// assert_eq!(names.map(|(name, _)| name.0).collect::<Vec<_>>(), ["player", "sign"]);
+    /// assert_eq!(healthy.map(|(entity, _)| entity).collect::<Vec<_>>(), [player]);
+    /// ```
+    #[must_use]
+    pub fn query_ref<Q: ReadOnlyQuery>(&self) -> QueryIter<'_, Q, (), R> {
+        QueryIter {
+            fetch: Q::fetch_ref(self),
+            filter: (),
+            rc: &self.rc,
+            generations: &self.generations,
+            index: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Query components of all entities that have all of them and match filter F
+    ///
+    /// ```
+    /// use ecs::{Data, Or, With, Without};
+    ///
+    /// struct Brain(u32);
+    /// struct Stunned;
+    /// struct Player;
+    /// struct Enemy;
+    ///
+    /// let mut world = Data::new();
+    /// for i in 0..4 {
+    ///     let entity = world.entity();
+    ///     world.insert(entity, Brain(i));
+    ///     if i % 2 == 0 {
+    ///         world.insert(entity, Stunned);
+    ///     }
+    ///     if i < 2 {
+    ///         world.insert(entity, Player);
+    ///     } else {
+    ///         world.insert(entity, Enemy);
+    ///     }
+    /// }
+    /// let awake: Vec<_> = world.query_filtered::<&Brain, Without<Stunned>>().map(|b| b.0).collect();
+    /// assert_eq!(awake, [1, 3]);
+    /// let stunned_enemies = world.query_filtered::<&Brain, (With<Stunned>, With<Enemy>)>().count();
+    /// assert_eq!(stunned_enemies, 1);
+    /// let all = world.query_filtered::<&Brain, Or<(With<Player>, With<Enemy>)>>().count();
+    /// assert_eq!(all, 4);
+    ///
+    /// for brain in world.query_filtered::<&mut Brain, (With<Brain>, Without<Stunned>)>() {
+    ///     brain.0 += 10;
+    /// }
+    /// let brains: Vec<_> = world.query::<&Brain>().map(|b| b.0).collect();
+    /// assert_eq!(brains, [0, 11, 2, 13]);
+    /// ```
+    /// # Panics
+    /// Panics if query accesses the same component type more than once and at least one access is mutable.
+    #[track_caller]
+    pub fn query_filtered<Q: Query, F: Filter>(&mut self) -> QueryIter<'_, Q, F, R> {
+        // Tick after current one is the oldest tick, so all changes are matched
+        self.query_since(self.tick.wrapping_add(1))
+    }
+
+    /// Query components of all entities that match filter F,
+    /// where [`Added`] and [`Changed`] filters match components stamped at tick since or later.
+    /// Systems remember tick returned by [`Data::advance_tick`] after they run and pass it as since.
+    /// Ticks wrap around, so ticks are compared by their age relative to current tick
+    /// and stamps older than `u32::MAX` ticks are not told apart from recent ones.
+    ///
+    /// ```
+    /// use ecs::{Added, Changed, Data, Entity};
+    ///
+    /// struct Health(u32);
+    /// struct Armor;
+    ///
+    /// let mut world = Data::new();
+    /// let player = world.entity();
+    /// world.insert(player, Health(10));
+    /// let rock = world.entity();
+    /// world.insert(rock, Health(100));
+    ///
+    /// let since = world.advance_tick();
+    /// assert_eq!(world.query_since::<&Health, Changed<Health>>(since).count(), 0);
+    ///
+    /// world.get_mut::<Health>(player).unwrap().0 -= 1;
+    /// let enemy = world.entity();
+    /// world.insert(enemy, Health(5));
+    /// let changed: Vec<_> = world.query_since::<&Health, Changed<Health>>(since).map(|h| h.0).collect();
+    /// assert_eq!(changed, [9, 5]);
+    /// let added: Vec<_> = world.query_since::<&Health, Added<Health>>(since).map(|h| h.0).collect();
+    /// assert_eq!(added, [5]);
+    ///
+    /// let since = world.advance_tick();
+    /// for health in world.query::<&mut Health>() {
+    ///     health.0 += 1;
+    /// }
+    /// assert_eq!(world.query_since::<&Health, Changed<Health>>(since).count(), 3);
+    /// assert_eq!(world.query_since::<&Health, Added<Health>>(since).count(), 0);
+    ///
+    /// let since = world.advance_tick();
+    /// world.get_mut::<Health>(rock).unwrap().0 = 0;
+    /// for health in world.query_since::<&mut Health, Changed<Health>>(since) {
+    ///     health.0 = 50;
+    /// }
+    /// assert_eq!(world.get::<Health>(rock).unwrap().0, 50);
+    /// assert_eq!(world.get::<Health>(player).unwrap().0, 10);
+    ///
+    /// // Only components of entities that matched whole query are marked as changed
+    /// world.insert(player, Armor);
+    /// let since = world.advance_tick();
+    /// for (health, _) in world.query::<(&mut Health, &Armor)>() {
+    ///     health.0 += 1;
+    /// }
+    /// let changed: Vec<_> = world.query_since::<Entity, Changed<Health>>(since).collect();
+    /// assert_eq!(changed, [player]);
+    /// ```
+    /// # Panics
+    /// Panics if query accesses the same component type more than once and at least one access is mutable.
+    #[track_caller]
+    pub fn query_since<Q: Query, F: Filter>(&mut self, since: u32) -> QueryIter<'_, Q, F, R> {
+        let mut access = Vec::new();
+        Q::access(&mut access);
+        for (i, (id, mutable)) in access.iter().enumerate() {
+            assert!(
+                !access[..i]
+                    .iter()
+                    .any(|(other, other_mutable)| other == id && (*mutable || *other_mutable)),
+                "query {} aliases mutable access to component",
+                core::any::type_name::<Q>()
+            );
+        }
+        QueryIter {
+            fetch: Q::fetch(self),
+            filter: F::fetch(self, since),
+            rc: &self.rc,
+            generations: &self.generations,
+            index: 0,
+            marker: PhantomData,
+        }
+    }
+}
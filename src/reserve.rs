@@ -0,0 +1,115 @@
+//! Lock-free reservation of entities through shared reference.
+
+use crate::{Data, Entity, RefCount};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Counters shared by data with its commands
+#[derive(Default)]
+pub(crate) struct Reservations {
+    // Number of entities reserved since last flush,
+    // taken first from the end of free list, then from new slots
+    pub(crate) count: AtomicUsize,
+    // Number of flushes, entities can be reserved only from state of data since last flush
+    pub(crate) flushes: AtomicUsize,
+}
+
+/// Handle reserving entities of [`Data`], which can be shared between threads.
+/// Reserved entities are spawned by next [`Data::flush`], [`Data::entity`] or [`Data::despawn`].
+///
+/// ```
+/// use ecs::Data;
+///
+/// let mut world = Data::new();
+/// let old = world.entity();
+/// world.despawn(old);
+///
+/// let reserver = world.reserver();
+/// let mut entities: Vec<_> = std::thread::scope(|s| {
+///     let workers: Vec<_> = (0..4).map(|_| s.spawn(|| reserver.reserve())).collect();
+///     workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+/// });
+/// world.flush();
+///
+/// entities.sort();
+/// entities.dedup();
+/// assert_eq!(entities.len(), 4);
+/// assert!(entities.iter().all(|&entity| world.is_alive(entity)));
+/// assert!(!world.is_alive(old));
+/// ```
+#[derive(Clone, Copy)]
+pub struct EntityReserver<'a> {
+    reserved: &'a AtomicUsize,
+    free: &'a [u32],
+    generations: &'a [u32],
+}
+
+impl EntityReserver<'_> {
+    /// Reserve entity, reusing slot of despawned entity if there is one
+    /// # Panics
+    /// Panics if number of entities overflows u32.
+    #[must_use]
+    pub fn reserve(&self) -> Entity {
+        let n = self.reserved.fetch_add(1, Ordering::Relaxed);
+        if let Some(i) = n.checked_sub(self.free.len()) {
+            return Entity {
+                index: u32::try_from(self.generations.len() + i).unwrap(),
+                generation: 0,
+            };
+        }
+        let index = self.free[self.free.len() - 1 - n];
+        Entity {
+            index,
+            generation: self.generations[usize::try_from(index).unwrap()],
+        }
+    }
+}
+
+impl<R: RefCount> Data<R> {
+    /// Get handle reserving entities through shared reference
+    #[must_use]
+    pub fn reserver(&self) -> EntityReserver<'_> {
+        EntityReserver {
+            reserved: &self.reserved.count,
+            free: &self.free,
+            generations: &self.generations,
+        }
+    }
+
+    /// Reserve entity through shared reference, see [`EntityReserver`].
+    /// Reserved entity is not alive until data is flushed, so components can not be inserted yet.
+    ///
+    /// ```
+    /// use ecs::Data;
+    ///
+    /// let mut world = Data::new();
+    /// let old = world.entity();
+    /// world.despawn(old);
+    ///
+    /// let reused = world.reserve_entity();
+    /// let new = world.reserve_entity();
+    /// assert!(!world.is_alive(reused) && !world.is_alive(new));
+    /// world.flush();
+    /// world.insert(reused, 1u32);
+    /// world.insert(new, 2u32);
+    /// assert_eq!(world.query::<&u32>().count(), 2);
+    /// ```
+    /// # Panics
+    /// Panics if number of entities overflows u32.
+    #[must_use]
+    pub fn reserve_entity(&self) -> Entity {
+        self.reserver().reserve()
+    }
+
+    // Spawn all reserved entities, must be called before free list or slots change
+    pub(crate) fn flush_entities(&mut self) {
+        self.reserved.flushes.fetch_add(1, Ordering::Relaxed);
+        let reserved = self.reserved.count.swap(0, Ordering::Relaxed);
+        let reused = reserved.min(self.free.len());
+        for index in self.free.drain(self.free.len() - reused..) {
+            self.rc[usize::try_from(index).unwrap()] = R::ONE;
+        }
+        for _ in reused..reserved {
+            self.push_slot(R::ONE);
+        }
+    }
+}
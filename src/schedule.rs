@@ -0,0 +1,190 @@
+//! Ordered execution of systems.
+
+use crate::system::{IntoSystem, System};
+use crate::{Data, EcsError, RefCount};
+use alloc::{boxed::Box, collections::BTreeSet, vec::Vec};
+
+/// Stage of [`Schedule`], stages run in order in which they are declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Systems preparing the frame, e.g. reading input
+    PreUpdate,
+    /// Systems of game logic
+    Update,
+    /// Systems reacting to game logic, e.g. synchronizing transforms
+    PostUpdate,
+    /// Systems drawing the frame
+    Render,
+}
+
+/// Handle of system added to [`Schedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemId(usize);
+
+/// Systems grouped into stages, ordered by constraints between systems.
+/// Systems of the same stage without constraints run in order in which they were added.
+/// Data is flushed after each stage, see [`Data::flush`].
+///
+/// ```
+/// use ecs::system::ResMut;
+/// use ecs::{Data, EcsError, Schedule, Stage};
+///
+/// struct Log(Vec<&'static str>);
+///
+/// fn input(mut log: ResMut<Log>) {
+///     log.0.push("input");
+/// }
+/// fn physics(mut log: ResMut<Log>) {
+///     log.0.push("physics");
+/// }
+/// fn ai(mut log: ResMut<Log>) {
+///     log.0.push("ai");
+/// }
+/// fn render(mut log: ResMut<Log>) {
+///     log.0.push("render");
+/// }
+///
+/// let mut world = Data::new();
+/// world.insert_resource(Log(Vec::new()));
+///
+/// let mut schedule = Schedule::new();
+/// schedule.add_system(Stage::Render, render);
+/// let physics = schedule.add_system(Stage::Update, physics);
+/// let ai = schedule.add_system(Stage::Update, ai);
+/// schedule.add_system(Stage::PreUpdate, input);
+/// schedule.after(physics, ai);
+/// schedule.run(&mut world).unwrap();
+/// assert_eq!(world.resource::<Log>().unwrap().0, ["input", "ai", "physics", "render"]);
+///
+/// schedule.before(physics, ai);
+/// assert!(matches!(schedule.run(&mut world), Err(EcsError::SystemCycle(_))));
+///
+/// let mut stages = Schedule::new();
+/// let render = stages.add_system(Stage::Render, render);
+/// let input = stages.add_system(Stage::PreUpdate, input);
+/// stages.before(render, input);
+/// assert!(matches!(stages.build(), Err(EcsError::StageOrder(_, _))));
+/// ```
+pub struct Schedule<R: RefCount = u8> {
+    systems: Vec<(Stage, Box<dyn System<R>>)>,
+    // Pairs of systems where first system runs before second
+    constraints: Vec<(SystemId, SystemId)>,
+    // Indices of systems in order of execution, None if it was not built since last change
+    order: Option<Vec<usize>>,
+}
+
+impl<R: RefCount> Default for Schedule<R> {
+    fn default() -> Self {
+        Self {
+            systems: Vec::new(),
+            constraints: Vec::new(),
+            order: None,
+        }
+    }
+}
+
+impl Schedule {
+    /// Initialize empty schedule
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<R: RefCount> Schedule<R> {
+    /// Add system to stage
+    /// # Panics
+    /// Panics if system can not be created, see [`IntoSystem::into_system`].
+    #[track_caller]
+    pub fn add_system<M>(
+        &mut self,
+        stage: Stage,
+        system: impl IntoSystem<R, M, System: 'static>,
+    ) -> SystemId {
+        self.systems.push((stage, Box::new(system.into_system())));
+        self.order = None;
+        SystemId(self.systems.len() - 1)
+    }
+
+    /// Make system a run before system b
+    pub fn before(&mut self, a: SystemId, b: SystemId) {
+        self.constraints.push((a, b));
+        self.order = None;
+    }
+
+    /// Make system a run after system b
+    pub fn after(&mut self, a: SystemId, b: SystemId) {
+        self.before(b, a);
+    }
+
+    /// Order systems by stages and constraints, [`Schedule::run`] builds schedule if it changed
+    /// # Errors
+    /// Returns error if constraints form a cycle, or if system is constrained
+    /// to run before system of earlier stage.
+    /// # Panics
+    /// Panics if system id was not returned by this schedule.
+    pub fn build(&mut self) -> Result<(), EcsError> {
+        if self.order.is_some() {
+            return Ok(());
+        }
+        let n = self.systems.len();
+        let mut edges = alloc::vec![Vec::new(); n];
+        let mut incoming = alloc::vec![0usize; n];
+        for &(SystemId(a), SystemId(b)) in &self.constraints {
+            match self.systems[a].0.cmp(&self.systems[b].0) {
+                core::cmp::Ordering::Less => {}
+                core::cmp::Ordering::Greater => {
+                    return Err(EcsError::StageOrder(
+                        self.systems[a].1.name(),
+                        self.systems[b].1.name(),
+                    ));
+                }
+                core::cmp::Ordering::Equal => {
+                    edges[a].push(b);
+                    incoming[b] += 1;
+                }
+            }
+        }
+        // Constraints are only between systems of the same stage,
+        // so taking ready system of earliest stage keeps stages in order
+        let mut ready: BTreeSet<(Stage, usize)> = (0..n)
+            .filter(|&i| incoming[i] == 0)
+            .map(|i| (self.systems[i].0, i))
+            .collect();
+        let mut order = Vec::with_capacity(n);
+        while let Some((_, i)) = ready.pop_first() {
+            order.push(i);
+            for &j in &edges[i] {
+                incoming[j] -= 1;
+                if incoming[j] == 0 {
+                    ready.insert((self.systems[j].0, j));
+                }
+            }
+        }
+        if let Some(i) = (0..n).find(|&i| incoming[i] > 0) {
+            return Err(EcsError::SystemCycle(self.systems[i].1.name()));
+        }
+        self.order = Some(order);
+        Ok(())
+    }
+
+    /// Run all systems, flushing data after each stage
+    /// # Errors
+    /// Returns error if schedule can not be built, see [`Schedule::build`].
+    /// # Panics
+    /// Panics if system id was not returned by this schedule.
+    pub fn run(&mut self, data: &mut Data<R>) -> Result<(), EcsError> {
+        self.build()?;
+        let mut stage = None;
+        for &i in self.order.as_ref().unwrap() {
+            let (system_stage, system) = &mut self.systems[i];
+            if stage.is_some_and(|stage| stage != *system_stage) {
+                data.flush();
+            }
+            stage = Some(*system_stage);
+            system.run(data);
+        }
+        data.flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Systems, which are functions that fetch their parameters from [`Data`].
+//!
+//! ```
+//! use ecs::system::{IntoSystem, Query, Res, System};
+//! use ecs::{Changed, Commands, Data, Entity};
+//!
+//! struct Position(f64);
+//! struct Velocity(f64);
+//! struct Time(f64);
+//! struct Moved;
+//!
+//! fn movement(mut q: Query<(&mut Position, &Velocity)>, time: Res<Time>) {
+//!     for (position, velocity) in q.iter() {
+//!         position.0 += velocity.0 * time.0;
+//!     }
+//! }
+//!
+//! fn mark_moved(q: Query<Entity, Changed<Position>>, commands: &mut Commands) {
+//!     for entity in q {
+//!         commands.insert(entity, Moved);
+//!     }
+//! }
+//!
+//! let mut world = Data::new();
+//! world.insert_resource(Time(0.5));
+//! let player = world.entity();
+//! world.insert(player, Position(0.));
+//! world.insert(player, Velocity(2.));
+//!
+//! let mut movement = movement.into_system();
+//! let mut mark_moved = mark_moved.into_system();
+//! mark_moved.run(&mut world);
+//! world.remove::<Moved>(player);
+//!
+//! mark_moved.run(&mut world);
+//! assert!(!world.contains::<Moved>(player));
+//! movement.run(&mut world);
+//! mark_moved.run(&mut world);
+//! assert!(world.contains::<Moved>(player));
+//! assert_eq!(world.get::<Position>(player).unwrap().0, 1.);
+//! ```
+
+use crate::{Commands, Data, Filter, QueryIter, RefCount};
+use alloc::vec::Vec;
+use core::{any::TypeId, marker::PhantomData};
+
+/// System that runs on [`Data`]
+pub trait System<R: RefCount = u8> {
+    /// Name of the system
+    fn name(&self) -> &'static str;
+
+    /// Run the system
+    fn run(&mut self, data: &mut Data<R>);
+}
+
+/// Conversion into [`System`], implemented for systems and for functions
+/// whose parameters implement [`SystemParam`].
+/// M distinguishes implementations for functions with different parameters.
+pub trait IntoSystem<R: RefCount, M> {
+    /// System created from self
+    type System: System<R>;
+
+    /// Convert self into system
+    /// # Panics
+    /// Panics if parameters of function access the same component or resource type
+    /// more than once and at least one access is mutable.
+    fn into_system(self) -> Self::System;
+}
+
+impl<R: RefCount, S: System<R>> IntoSystem<R, ()> for S {
+    type System = S;
+
+    fn into_system(self) -> Self::System {
+        self
+    }
+}
+
+/// Parameter of function system fetched from [`Data`]
+///
+/// Implemented for [`Query`], [`Res`], [`ResMut`], `&mut Commands` and tuples of parameters.
+/// # Safety
+/// [`SystemParam::access`] must append every component and resource type accessed by
+/// [`SystemParam::get`], marked as mutable if it is mutably borrowed,
+/// because parameters of system are checked for aliasing mutable access by it alone.
+pub unsafe trait SystemParam<R: RefCount = u8> {
+    /// State kept by system between runs
+    type State;
+    /// Parameter passed to function
+    type Item<'w>;
+
+    /// Append component and resource types accessed by the parameter, true for mutable access
+    fn access(components: &mut Vec<(TypeId, bool)>, resources: &mut Vec<(TypeId, bool)>);
+
+    /// Create state before first run of system
+    fn init(data: &mut Data<R>) -> Self::State;
+
+    /// Get parameter, change detection filters match changes since tick since
+    /// # Safety
+    /// Data must outlive returned item and accesses of all parameters of system must not alias.
+    unsafe fn get(state: &mut Self::State, data: *mut Data<R>, since: u32) -> Self::Item<'_>;
+
+    /// Apply deferred changes after system ran
+    fn apply(state: &mut Self::State, data: &mut Data<R>);
+}
+
+/// Query parameter, iterates over components of entities matching query Q and filter F.
+/// [`crate::Added`] and [`crate::Changed`] filters match changes since previous run of the system.
+pub struct Query<'w, Q: crate::Query, F: Filter = (), R: RefCount = u8> {
+    data: *mut Data<R>,
+    since: u32,
+    marker: PhantomData<(&'w mut Data<R>, Q, F)>,
+}
+
+impl<Q: crate::Query, F: Filter, R: RefCount> Query<'_, Q, F, R> {
+    /// Iterate over components of matching entities
+    /// # Panics
+    /// Panics if query accesses the same component type more than once and at least one access is mutable.
+    #[track_caller]
+    pub fn iter(&mut self) -> QueryIter<'_, Q, F, R> {
+        // Access of parameters was checked when system was created
+        unsafe { (*self.data).query_since(self.since) }
+    }
+}
+
+impl<'w, Q: crate::Query + 'w, F: Filter, R: RefCount> IntoIterator for Query<'w, Q, F, R> {
+    type Item = Q::Item<'w>;
+    type IntoIter = QueryIter<'w, Q, F, R>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        unsafe { (*self.data).query_since(self.since) }
+    }
+}
+
+unsafe impl<Q: crate::Query, F: Filter, R: RefCount> SystemParam<R> for Query<'_, Q, F, R> {
+    type State = ();
+    type Item<'w> = Query<'w, Q, F, R>;
+
+    fn access(components: &mut Vec<(TypeId, bool)>, _: &mut Vec<(TypeId, bool)>) {
+        Q::access(components);
+    }
+
+    fn init(_: &mut Data<R>) -> Self::State {}
+
+    unsafe fn get((): &mut Self::State, data: *mut Data<R>, since: u32) -> Self::Item<'_> {
+        Query {
+            data,
+            since,
+            marker: PhantomData,
+        }
+    }
+
+    fn apply((): &mut Self::State, _: &mut Data<R>) {}
+}
+
+/// Resource parameter
+pub struct Res<'w, T>(&'w T);
+
+impl<T> core::ops::Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+unsafe impl<T: 'static, R: RefCount> SystemParam<R> for Res<'_, T> {
+    type State = ();
+    type Item<'w> = Res<'w, T>;
+
+    fn access(_: &mut Vec<(TypeId, bool)>, resources: &mut Vec<(TypeId, bool)>) {
+        resources.push((TypeId::of::<T>(), false));
+    }
+
+    fn init(_: &mut Data<R>) -> Self::State {}
+
+    /// # Panics
+    /// Panics if resource was not inserted.
+    unsafe fn get((): &mut Self::State, data: *mut Data<R>, _: u32) -> Self::Item<'_> {
+        match unsafe { (*data).resource() } {
+            Ok(resource) => Res(resource),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn apply((): &mut Self::State, _: &mut Data<R>) {}
+}
+
+/// Mutable resource parameter
+pub struct ResMut<'w, T>(&'w mut T);
+
+impl<T> core::ops::Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T> core::ops::DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+unsafe impl<T: 'static, R: RefCount> SystemParam<R> for ResMut<'_, T> {
+    type State = ();
+    type Item<'w> = ResMut<'w, T>;
+
+    fn access(_: &mut Vec<(TypeId, bool)>, resources: &mut Vec<(TypeId, bool)>) {
+        resources.push((TypeId::of::<T>(), true));
+    }
+
+    fn init(_: &mut Data<R>) -> Self::State {}
+
+    /// # Panics
+    /// Panics if resource was not inserted.
+    unsafe fn get((): &mut Self::State, data: *mut Data<R>, _: u32) -> Self::Item<'_> {
+        match unsafe { (*data).resource_mut() } {
+            Ok(resource) => ResMut(resource),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn apply((): &mut Self::State, _: &mut Data<R>) {}
+}
+
+// Commands are created before each run and applied after it
+unsafe impl<R: RefCount> SystemParam<R> for &mut Commands<R> {
+    type State = Option<Commands<R>>;
+    type Item<'w> = &'w mut Commands<R>;
+
+    fn access(_: &mut Vec<(TypeId, bool)>, _: &mut Vec<(TypeId, bool)>) {}
+
+    fn init(_: &mut Data<R>) -> Self::State {
+        None
+    }
+
+    unsafe fn get(state: &mut Self::State, data: *mut Data<R>, _: u32) -> Self::Item<'_> {
+        state.insert(unsafe { (*data).commands() })
+    }
+
+    fn apply(state: &mut Self::State, data: &mut Data<R>) {
+        if let Some(commands) = state.take() {
+            data.apply(commands);
+        }
+    }
+}
+
+/// System created from function with parameters P
+pub struct FunctionSystem<F, P: SystemParam<R>, R: RefCount = u8> {
+    function: F,
+    state: Option<P::State>,
+    // Tick returned by Data::advance_tick after previous run
+    last_run: u32,
+}
+
+/// Function that can be called with items of parameters P
+pub trait SystemFunction<R: RefCount, P: SystemParam<R>> {
+    /// Call function with parameters
+    fn call(&mut self, params: P::Item<'_>);
+}
+
+impl<R: RefCount, F: SystemFunction<R, P>, P: SystemParam<R>> System<R>
+    for FunctionSystem<F, P, R>
+{
+    fn name(&self) -> &'static str {
+        core::any::type_name::<F>()
+    }
+
+    fn run(&mut self, data: &mut Data<R>) {
+        let state = self.state.get_or_insert_with(|| P::init(data));
+        // Access of parameters was checked when system was created
+        let params = unsafe { P::get(state, core::ptr::from_mut(data), self.last_run) };
+        self.function.call(params);
+        P::apply(state, data);
+        self.last_run = data.advance_tick();
+    }
+}
+
+impl<R: RefCount, F: SystemFunction<R, P>, P: SystemParam<R>> IntoSystem<R, fn(P)> for F {
+    type System = FunctionSystem<F, P, R>;
+
+    #[track_caller]
+    fn into_system(self) -> Self::System {
+        let mut components = Vec::new();
+        let mut resources = Vec::new();
+        P::access(&mut components, &mut resources);
+        for access in [&components, &resources] {
+            for (i, (id, mutable)) in access.iter().enumerate() {
+                assert!(
+                    !access[..i]
+                        .iter()
+                        .any(|(other, other_mutable)| other == id && (*mutable || *other_mutable)),
+                    "system {} aliases mutable access to component or resource",
+                    core::any::type_name::<F>()
+                );
+            }
+        }
+        FunctionSystem {
+            function: self,
+            state: None,
+            last_run: 0,
+        }
+    }
+}
+
+macro_rules! impl_system_param {
+    ($($p:ident),*) => {
+        unsafe impl<R: RefCount, $($p: SystemParam<R>),*> SystemParam<R> for ($($p,)*) {
+            type State = ($($p::State,)*);
+            type Item<'w> = ($($p::Item<'w>,)*);
+
+            #[allow(unused_variables)]
+            fn access(components: &mut Vec<(TypeId, bool)>, resources: &mut Vec<(TypeId, bool)>) {
+                $($p::access(components, resources);)*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn init(data: &mut Data<R>) -> Self::State {
+                ($($p::init(data),)*)
+            }
+
+            #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+            unsafe fn get(
+                state: &mut Self::State,
+                data: *mut Data<R>,
+                since: u32,
+            ) -> Self::Item<'_> {
+                let ($($p,)*) = state;
+                ($(unsafe { $p::get($p, data, since) },)*)
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn apply(state: &mut Self::State, data: &mut Data<R>) {
+                let ($($p,)*) = state;
+                $($p::apply($p, data);)*
+            }
+        }
+
+        impl<R: RefCount, Func, $($p: SystemParam<R>),*> SystemFunction<R, ($($p,)*)> for Func
+        where
+            Func: FnMut($($p),*) + FnMut($($p::Item<'_>),*),
+        {
+            fn call(&mut self, params: ($($p::Item<'_>,)*)) {
+                // Calls function through its second FnMut bound
+                #[allow(non_snake_case)]
+                fn call_inner<$($p),*>(mut function: impl FnMut($($p),*), ($($p,)*): ($($p,)*)) {
+                    function($($p),*);
+                }
+                call_inner(self, params);
+            }
+        }
+    };
+}
+
+impl_system_param!();
+impl_system_param!(A);
+impl_system_param!(A, B);
+impl_system_param!(A, B, C);
+impl_system_param!(A, B, C, D);
+impl_system_param!(A, B, C, D, E);
+impl_system_param!(A, B, C, D, E, F);
+impl_system_param!(A, B, C, D, E, F, G);
+impl_system_param!(A, B, C, D, E, F, G, H);